@@ -1,5 +1,6 @@
-use crate::charclass::build_chars;
+use crate::charclass::{build_chars, build_class, build_literal, build_named, build_property};
 use crate::distribution::{Dist, DistLink};
+use crate::error::CompileError;
 use crate::parser::Rule;
 use itertools::Itertools;
 use pest::iterators::Pair;
@@ -15,18 +16,37 @@ pub struct AstNode {
 pub enum Kind {
     AnchorEnd,
     AnchorStart,
-    Alternation(Box<AstNode>, Box<AstNode>),
+    /// Alternatives, with an optional distribution over the branches of the chain
+    Alternation(Box<AstNode>, Box<AstNode>, Option<DistLink>),
     Concatenation(Box<AstNode>, Box<AstNode>),
     ExactQuantifier(u64),
+    RangeQuantifier(u64, u64),
     Literal(char),
     Dot,
     Split,
     Start,
     Terminal,
     Classified(Box<AstNode>, Option<DistLink>),
+    /// `.` with a distribution over an explicit alphabet, a negated class once flags are applied
+    DotClassified(Vec<char>, DistLink),
     Class(bool, Vec<char>),
+    /// Class that unites a negated part, i.e. `[\Wa]`, by the characters it excludes,
+    /// or matches when negated, until flags are applied
+    ExcludingClass(bool, Vec<char>),
+    /// Class matched up to case, so that its distribution applies to the folded character
+    FoldedClass(bool, Vec<char>),
     Quantified(Box<AstNode>, Box<AstNode>, Option<DistLink>),
     Quantifier(char),
+    /// Capturing group, numbered from 1 in the order it opens in source
+    Group(usize, Option<String>, Box<AstNode>),
+    /// NFA state where a capturing group opens
+    GroupOpen(usize, Option<String>),
+    /// NFA state where a capturing group closes
+    GroupClose(usize),
+    /// Inline flags turned on and off, up to the end of the enclosing group
+    Flags(Vec<char>, Vec<char>),
+    /// Non-capturing group, with flags turned on and off within it
+    FlagGroup(Vec<char>, Vec<char>, Box<AstNode>),
 }
 
 impl fmt::Display for Kind {
@@ -34,31 +54,47 @@ impl fmt::Display for Kind {
         match &self {
             Kind::Literal(c) => write!(f, "{}", c),
             Kind::Dot => write!(f, "."),
-            Kind::Class(neg, c) if c.len() > 5 => match neg {
+            Kind::Class(neg, c) | Kind::FoldedClass(neg, c) if c.len() > 5 => match neg {
                 true => write!(f, "[^{}..]", c.iter().take(3).join("")),
                 false => write!(f, "[{}..]", c.iter().take(4).join("")),
             },
-            Kind::Class(neg, c) => match neg {
+            Kind::Class(neg, c) | Kind::FoldedClass(neg, c) => match neg {
                 true => write!(f, "[^{}]", c.iter().join("")),
                 false => write!(f, "[{}]", c.iter().join("")),
             },
+            Kind::ExcludingClass(neg, c) => Kind::Class(!neg, c.clone()).fmt(f),
             Kind::Classified(l, Some(d)) => write!(f, "[{}{}]", l, d),
             Kind::Classified(l, None) => write!(f, "[{}]", l),
+            Kind::DotClassified(_, d) => write!(f, "[.{}]", d),
             Kind::Concatenation(l, r) => write!(f, "{}{}.", l, r),
-            Kind::Quantified(r, l, Some(d)) => write!(f, "{}{{{}{}}}", l, r, d),
+            Kind::Quantified(r, l, Some(d)) => match r.kind {
+                Kind::Quantifier(_) => write!(f, "{}{}{}", l, r, d),
+                _ => write!(f, "{}{{{}{}}}", l, r, d),
+            },
             Kind::Quantified(r, l, None) => match r.kind {
                 Kind::Quantifier(_) => write!(f, "{}{}", l, r),
-                Kind::ExactQuantifier(_) => write!(f, "{}{{{}}}", l, r),
+                Kind::ExactQuantifier(_) | Kind::RangeQuantifier(_, _) => {
+                    write!(f, "{}{{{}}}", l, r)
+                }
                 _ => unreachable!(),
             },
             Kind::Quantifier(c) => write!(f, "{}", c),
             Kind::ExactQuantifier(n) => write!(f, "{}", n),
-            Kind::Alternation(l, r) => write!(f, "{}|{}", l, r),
+            Kind::RangeQuantifier(n, u64::MAX) => write!(f, "{},", n),
+            Kind::RangeQuantifier(n, m) => write!(f, "{},{}", n, m),
+            Kind::Alternation(l, r, Some(d)) => write!(f, "{}|{}{}", l, r, d),
+            Kind::Alternation(l, r, None) => write!(f, "{}|{}", l, r),
             Kind::Split => write!(f, "|"),
             Kind::Terminal => write!(f, ""),
             Kind::Start => write!(f, ""),
             Kind::AnchorStart => write!(f, "^"),
             Kind::AnchorEnd => write!(f, "$"),
+            Kind::Group(_, Some(name), inner) => write!(f, "(?<{}>{})", name, inner),
+            Kind::Group(_, None, inner) => write!(f, "({})", inner),
+            Kind::GroupOpen(_, _) => write!(f, "("),
+            Kind::GroupClose(_) => write!(f, ")"),
+            Kind::Flags(on, off) => write!(f, "(?{})", flag_set(on, off)),
+            Kind::FlagGroup(on, off, inner) => write!(f, "(?{}:{})", flag_set(on, off), inner),
             // See also fmt::Display for Dist
         }
     }
@@ -70,20 +106,20 @@ impl fmt::Display for AstNode {
     }
 }
 
-pub fn build_ast_from_expr(pair: Pair<Rule>) -> AstNode {
+pub fn build_ast_from_expr(pair: Pair<Rule>) -> Result<AstNode, CompileError> {
     let rule = pair.as_rule();
-    match rule {
+    let node = match rule {
         Rule::Alternation => {
             let mut pair = pair.into_inner();
             let left = pair.next().unwrap();
-            let left_ast = build_ast_from_expr(left);
+            let left_ast = build_ast_from_expr(left)?;
 
             if let Some(right) = pair.next() {
-                let right_ast = build_ast_from_expr(right);
-                return AstNode {
+                let right_ast = build_ast_from_expr(right)?;
+                return Ok(AstNode {
                     length: left_ast.length + right_ast.length + 1,
-                    kind: Kind::Alternation(Box::new(left_ast), Box::new(right_ast)),
-                };
+                    kind: Kind::Alternation(Box::new(left_ast), Box::new(right_ast), None),
+                });
             }
             left_ast
         }
@@ -98,8 +134,8 @@ pub fn build_ast_from_expr(pair: Pair<Rule>) -> AstNode {
         Rule::Concat | Rule::Concats => {
             let mut pair = pair.into_inner();
             let (left, right) = pair.next_tuple().unwrap();
-            let left_ast = build_ast_from_expr(left);
-            let right_ast = build_ast_from_expr(right);
+            let left_ast = build_ast_from_expr(left)?;
+            let right_ast = build_ast_from_expr(right)?;
             AstNode {
                 length: left_ast.length + right_ast.length,
                 kind: Kind::Concatenation(Box::new(left_ast), Box::new(right_ast)),
@@ -107,12 +143,23 @@ pub fn build_ast_from_expr(pair: Pair<Rule>) -> AstNode {
         }
         Rule::Quantified => {
             let mut pair = pair.into_inner();
-            let left_ast = build_ast_from_expr(pair.next().unwrap());
+            let left_ast = build_ast_from_expr(pair.next().unwrap())?;
             // pair.next is ShortQuantifier or LongQuantifier
-            let quantifier_ast = build_ast_from_expr(pair.next().unwrap());
+            let quantifier_pair = pair.next().unwrap();
             // pair.next is Option<Dist>
-            let quantifier_dist = match pair.next() {
-                Some(pair) => Some(Dist::complete_from(&quantifier_ast.kind, pair)),
+            let dist_pair = pair.next();
+            let quantifier_ast = match &dist_pair {
+                // Hist lists the counts it allows, so the count may be left out
+                Some(dist) if quantifier_pair.as_str().is_empty() && is_histogram(dist) => {
+                    AstNode {
+                        length: 1,
+                        kind: Kind::ExactQuantifier(0),
+                    }
+                }
+                _ => build_ast_from_expr(quantifier_pair)?,
+            };
+            let quantifier_dist = match dist_pair {
+                Some(pair) => Some(Dist::complete_from(&quantifier_ast.kind, pair)?),
                 None => Dist::default_from(&quantifier_ast.kind),
             };
             AstNode {
@@ -124,45 +171,133 @@ pub fn build_ast_from_expr(pair: Pair<Rule>) -> AstNode {
                 ),
             }
         }
-        Rule::Literal | Rule::EscapedLiteral => {
-            let c = pair.as_str().chars().next().unwrap();
-            AstNode {
-                length: 1,
-                kind: Kind::Literal(c),
+        Rule::Group => {
+            let mut pair = pair.into_inner();
+            let mut first = pair.next().unwrap();
+            let (is_capturing, name, flags) = match first.as_rule() {
+                Rule::NonCapturing => {
+                    first = pair.next().unwrap();
+                    (false, None, (vec![], vec![]))
+                }
+                Rule::GroupFlags => {
+                    let flags = build_flags(first);
+                    first = pair.next().unwrap();
+                    (false, None, flags)
+                }
+                Rule::GroupName => {
+                    let name = first.into_inner().next().unwrap().as_str().to_string();
+                    first = pair.next().unwrap();
+                    (true, Some(name), (vec![], vec![]))
+                }
+                _ => (true, None, (vec![], vec![])),
+            };
+            let mut inner_ast = build_ast_from_expr(first)?;
+
+            // pair.next is Option<Dist>, weighing the alternatives of the group
+            if let Some(dist_pair) = pair.next() {
+                let span = dist_pair.as_span().into();
+                let dist = match inner_ast.kind {
+                    Kind::Alternation(_, _, _) => Dist::complete_from(&inner_ast.kind, dist_pair)?,
+                    _ => {
+                        return Err(CompileError::InvalidDistribution(
+                            span,
+                            "a distribution on a group needs alternatives to weigh".to_string(),
+                        ))
+                    }
+                };
+                if let Kind::Alternation(_, _, ref mut branch_dist) = inner_ast.kind {
+                    *branch_dist = Some(DistLink::Indexed(dist));
+                }
+            }
+
+            match is_capturing {
+                // a non-capturing group only groups, it leaves no trace once flags are applied
+                false => AstNode {
+                    length: inner_ast.length,
+                    kind: Kind::FlagGroup(flags.0, flags.1, Box::new(inner_ast)),
+                },
+                true => AstNode {
+                    length: inner_ast.length + 2,
+                    // numbered once the whole pattern is parsed, see number_groups
+                    kind: Kind::Group(0, name, Box::new(inner_ast)),
+                },
             }
         }
+        Rule::Literal | Rule::EscapedLiteral | Rule::EscapedCode => AstNode {
+            length: 1,
+            kind: Kind::Literal(build_literal(pair)?),
+        },
         Rule::Dot => AstNode {
             length: 1,
             kind: Kind::Dot,
         },
-        Rule::LongClass | Rule::LongClassNeg => {
+        Rule::BareClass => {
             let mut pair = pair.into_inner();
+            let class_pair = pair.next().unwrap();
 
-            let left_ast = match rule {
-                Rule::LongClass => build_ast_from_expr(pair.next().unwrap()),
-                Rule::LongClassNeg => {
-                    let mut ast = build_ast_from_expr(pair.next().unwrap());
-                    if let Kind::Class(false, c) = ast.kind {
-                        ast.kind = Kind::Class(true, c);
-                    }
-                    ast
-                }
-                _ => unreachable!(),
+            // pair.next is Option<Dist>
+            let dist_pair = match pair.next() {
+                Some(dist_pair) => dist_pair,
+                None => return build_ast_from_expr(class_pair),
+            };
+            let is_dot = class_pair.as_rule() == Rule::Dot;
+            let class_ast = match class_pair.as_rule() {
+                // `.` is the negation of the empty class, less the alphabet the dist names
+                Rule::Dot => match alphabet(&dist_pair) {
+                    chars if chars.is_empty() => return Err(CompileError::InvalidDistribution(
+                        dist_pair.as_span().into(),
+                        "a distribution on . needs an explicit alphabet, i.e. .~Cat(a=0.4,b=0.6)"
+                            .to_string(),
+                    )),
+                    chars => AstNode {
+                        length: 1,
+                        kind: Kind::Class(true, chars),
+                    },
+                },
+                _ => build_ast_from_expr(class_pair)?,
             };
+            let dist = DistLink::Indexed(Dist::complete_from(&class_ast.kind, dist_pair)?);
+            AstNode {
+                length: 1,
+                kind: match (is_dot, class_ast.kind) {
+                    // whether `.` matches a newline is up to the flags, see apply_flags
+                    (true, Kind::Class(_, chars)) => Kind::DotClassified(chars, dist),
+                    (_, kind) => Kind::Classified(
+                        Box::new(AstNode {
+                            length: class_ast.length,
+                            kind,
+                        }),
+                        Some(dist),
+                    ),
+                },
+            }
+        }
+        Rule::LongClass | Rule::LongClassNeg => {
+            let mut pair = pair.into_inner();
+            let class_pair = pair.next().unwrap();
+            let is_negate = rule == Rule::LongClassNeg;
 
             // pair.next is Option<Dist>
-            let class_dist = match pair.next() {
-                Some(pair) => Some(Dist::complete_from(&left_ast.kind, pair)),
+            let dist_pair = pair.next();
+            let left_ast = match dist_pair {
+                // only a distribution over the members needs them all listed
+                None => AstNode {
+                    length: 1,
+                    kind: match build_class(class_pair)? {
+                        (true, excluded) => Kind::ExcludingClass(is_negate, excluded),
+                        (false, members) => Kind::Class(is_negate, members),
+                    },
+                },
+                Some(_) => AstNode {
+                    length: 1,
+                    kind: Kind::Class(is_negate, build_chars(class_pair)?),
+                },
+            };
+            let class_dist = match dist_pair {
+                Some(pair) => Some(Dist::complete_from(&left_ast.kind, pair)?),
                 None => None,
             };
             match class_dist {
-                Some(Dist::Categorical(_)) => AstNode {
-                    length: 1,
-                    kind: Kind::Classified(
-                        Box::new(left_ast),
-                        Some(DistLink::Indexed(class_dist.unwrap())),
-                    ),
-                },
                 Some(dist) => AstNode {
                     length: 1,
                     kind: Kind::Classified(Box::new(left_ast), Some(DistLink::Indexed(dist))),
@@ -170,9 +305,30 @@ pub fn build_ast_from_expr(pair: Pair<Rule>) -> AstNode {
                 None => left_ast,
             }
         }
-        Rule::CharacterClass | Rule::ShortClass | Rule::PosixClass => AstNode {
+        Rule::CharacterClass => AstNode {
             length: 1,
-            kind: Kind::Class(false, build_chars(pair)),
+            kind: Kind::Class(false, build_chars(pair)?),
+        },
+        Rule::ShortClass | Rule::PosixClass => {
+            let (is_negate, chars) = build_named(pair)?;
+            AstNode {
+                length: 1,
+                kind: Kind::Class(is_negate, chars),
+            }
+        }
+        Rule::PropertyClass => {
+            let (is_negate, chars) = build_property(pair)?;
+            AstNode {
+                length: 1,
+                kind: Kind::Class(is_negate, chars),
+            }
+        }
+        Rule::Flags => AstNode {
+            length: 0,
+            kind: {
+                let (on, off) = build_flags(pair);
+                Kind::Flags(on, off)
+            },
         },
         Rule::EOI => AstNode {
             length: 0,
@@ -187,12 +343,278 @@ pub fn build_ast_from_expr(pair: Pair<Rule>) -> AstNode {
         }
         Rule::ExactQuantifier => {
             let pair = pair.into_inner().next().unwrap();
-            let n = pair.as_str().parse::<u64>().unwrap();
             AstNode {
                 length: 1,
-                kind: Kind::ExactQuantifier(n),
+                kind: Kind::ExactQuantifier(parse_quantifier_param(pair)?),
             }
         }
-        _ => build_ast_from_expr(pair),
+        Rule::RangeQuantifier => {
+            let span = pair.as_span().into();
+            let mut pair = pair.into_inner();
+            let n_min = parse_quantifier_param(pair.next().unwrap())?;
+            let n_max = match pair.next().unwrap() {
+                // upper bound may be left out for unbounded repetition
+                p if p.as_str().is_empty() => u64::MAX,
+                p => parse_quantifier_param(p)?,
+            };
+            if n_max < n_min {
+                return Err(CompileError::InvalidQuantifier(
+                    span,
+                    format!("upper bound {} is below lower bound {}", n_max, n_min),
+                ));
+            }
+            AstNode {
+                length: 1,
+                kind: Kind::RangeQuantifier(n_min, n_max),
+            }
+        }
+        _ => build_ast_from_expr(pair)?,
+    };
+    Ok(node)
+}
+
+/// Inline flags in effect
+#[derive(Debug, Default, Clone, Copy)]
+struct Flags {
+    /// `i`, letters match in either case
+    case_insensitive: bool,
+    /// `s`, `.` matches a newline too
+    dot_matches_newline: bool,
+}
+
+impl Flags {
+    fn set(&mut self, on: &[char], off: &[char]) {
+        for (flags, value) in [(on, true), (off, false)] {
+            for flag in flags {
+                match flag {
+                    'i' => self.case_insensitive = value,
+                    's' => self.dot_matches_newline = value,
+                    // `x` only changes how the source reads, see parser::Verbose
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Apply inline flags to the nodes they cover, in the order of source, and drop them
+///
+/// Flags last up to the end of the group they are set in, across alternatives.
+pub fn apply_flags(asts: Vec<AstNode>) -> Vec<AstNode> {
+    fn visit(node: AstNode, flags: &mut Flags) -> Option<AstNode> {
+        let length = node.length;
+        let kind = match node.kind {
+            Kind::Flags(on, off) => {
+                flags.set(&on, &off);
+                return None;
+            }
+            Kind::FlagGroup(on, off, inner) => {
+                let mut scoped = *flags;
+                scoped.set(&on, &off);
+                return visit(*inner, &mut scoped);
+            }
+            // newline is one more member, that takes no mass, unless the flags let `.` match it
+            Kind::DotClassified(mut chars, mut dist) => {
+                if let (false, DistLink::Indexed(Dist::Categorical(prob_mass))) =
+                    (flags.dot_matches_newline, &mut dist)
+                {
+                    chars.push('\n');
+                    prob_mass.push(0.0);
+                }
+                let class = AstNode {
+                    length,
+                    kind: Kind::Class(true, chars),
+                };
+                return visit(
+                    AstNode {
+                        length,
+                        kind: Kind::Classified(Box::new(class), Some(dist)),
+                    },
+                    flags,
+                );
+            }
+            Kind::Group(index, name, inner) => {
+                let inner = visit(*inner, &mut flags.clone())?;
+                Kind::Group(index, name, Box::new(inner))
+            }
+            Kind::Concatenation(left, right) => {
+                // flags are followed by what they apply to, see Flags in the grammar
+                match (visit(*left, flags), visit(*right, flags)) {
+                    (Some(left), Some(right)) => {
+                        Kind::Concatenation(Box::new(left), Box::new(right))
+                    }
+                    (Some(node), None) | (None, Some(node)) => return Some(node),
+                    (None, None) => return None,
+                }
+            }
+            Kind::Alternation(left, right, dist) => {
+                let left = visit(*left, flags)?;
+                let right = visit(*right, flags)?;
+                Kind::Alternation(Box::new(left), Box::new(right), dist)
+            }
+            Kind::Quantified(quantifier, quantified, dist) => {
+                let quantified = visit(*quantified, flags)?;
+                Kind::Quantified(quantifier, Box::new(quantified), dist)
+            }
+            Kind::Literal(c) if flags.case_insensitive => match case_variants(c) {
+                variants if variants.len() > 1 => Kind::Class(false, variants),
+                _ => Kind::Literal(c),
+            },
+            // in either case, a character is only excluded if all its cases are
+            Kind::ExcludingClass(is_negate, excluded) => Kind::Class(
+                !is_negate,
+                match flags.case_insensitive {
+                    true => excluded
+                        .iter()
+                        .filter(|c| {
+                            case_variants(**c)
+                                .iter()
+                                .all(|v| excluded.binary_search(v).is_ok())
+                        })
+                        .copied()
+                        .collect(),
+                    false => excluded,
+                },
+            ),
+            Kind::Class(is_negate, chars) if flags.case_insensitive => Kind::Class(
+                is_negate,
+                chars.into_iter().flat_map(case_variants).unique().collect(),
+            ),
+            // members keep their index, as the distribution is over them
+            Kind::Classified(class, dist) if flags.case_insensitive => match class.kind {
+                Kind::Class(is_negate, chars) => Kind::Classified(
+                    Box::new(AstNode {
+                        length: class.length,
+                        kind: Kind::FoldedClass(is_negate, chars),
+                    }),
+                    dist,
+                ),
+                kind => Kind::Classified(
+                    Box::new(AstNode {
+                        length: class.length,
+                        kind,
+                    }),
+                    dist,
+                ),
+            },
+            // `.` is the negation of the empty class, newline included
+            Kind::Dot if flags.dot_matches_newline => Kind::Class(true, vec![]),
+            kind => kind,
+        };
+        Some(AstNode { length, kind })
+    }
+
+    let mut flags = Flags::default();
+    asts.into_iter()
+        .filter_map(|ast| visit(ast, &mut flags))
+        .collect()
+}
+
+/// The character and its other cases, that are single characters
+fn case_variants(c: char) -> Vec<char> {
+    let single = |case: String| {
+        let mut chars = case.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    [
+        Some(c),
+        single(c.to_lowercase().collect()),
+        single(c.to_uppercase().collect()),
+    ]
+    .into_iter()
+    .flatten()
+    .unique()
+    .collect()
+}
+
+/// Flags turned on and off by a FlagGroup or Flags pair
+fn build_flags(pair: Pair<Rule>) -> (Vec<char>, Vec<char>) {
+    let mut on = vec![];
+    let mut off = vec![];
+    for p in pair.into_inner() {
+        match p.as_rule() {
+            Rule::FlagsOn => on.extend(p.as_str().chars()),
+            Rule::FlagsOff => off.extend(p.as_str().chars()),
+            _ => {}
+        }
+    }
+    (on, off)
+}
+
+/// Flags as written in source, i.e. `is-x`
+fn flag_set(on: &[char], off: &[char]) -> String {
+    match off.is_empty() {
+        true => on.iter().collect(),
+        false => format!(
+            "{}-{}",
+            on.iter().collect::<String>(),
+            off.iter().collect::<String>()
+        ),
+    }
+}
+
+/// Number the capturing groups from 1, in the order they open in source
+pub fn number_groups(asts: &mut [AstNode]) {
+    fn visit(node: &mut AstNode, n: &mut usize) {
+        match &mut node.kind {
+            Kind::Group(index, _, inner) => {
+                *n += 1;
+                *index = *n;
+                visit(inner, n);
+            }
+            Kind::Concatenation(left, right) | Kind::Alternation(left, right, _) => {
+                visit(left, n);
+                visit(right, n);
+            }
+            Kind::Quantified(_, quantified, _) => visit(quantified, n),
+            _ => {}
+        }
+    }
+
+    let mut n = 0;
+    for ast in asts.iter_mut() {
+        visit(ast, &mut n);
+    }
+}
+
+/// True if the distribution pair is a Hist
+fn is_histogram(dist_pair: &Pair<Rule>) -> bool {
+    match dist_pair.clone().into_inner().next() {
+        Some(name) => name.as_str().eq_ignore_ascii_case("hist"),
+        None => false,
+    }
+}
+
+/// Characters named by the parameters of the distribution pair, in order
+fn alphabet(dist_pair: &Pair<Rule>) -> Vec<char> {
+    dist_pair
+        .clone()
+        .into_inner()
+        .filter(|p| p.as_rule() == Rule::NamedParam)
+        .filter_map(|p| {
+            let mut chars = p.as_str().chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), Some('=')) if c != '.' => Some(c),
+                _ => None,
+            }
+        })
+        .unique()
+        .collect()
+}
+
+/// Parse the repetition count of a quantifier
+fn parse_quantifier_param(pair: Pair<Rule>) -> Result<u64, CompileError> {
+    let span = pair.as_span().into();
+    match pair.as_str() {
+        "" => Err(CompileError::InvalidQuantifier(
+            span,
+            "expected a repetition count".to_string(),
+        )),
+        n => n.parse::<u64>().map_err(|_| {
+            CompileError::InvalidQuantifier(span, format!("repetition count {} is too large", n))
+        }),
     }
 }
@@ -0,0 +1,245 @@
+use crate::{
+    ast::Kind,
+    distribution::{Dist, DistLink},
+    nfa::State,
+};
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of characters there are, i.e. unicode scalar values without the surrogates
+const N_CHARS: usize = char::MAX as usize + 1 - 0x800;
+
+/// Unigram distribution over characters, for the characters `.` matches
+///
+/// Without a background model `.` matches any character with probability one,
+/// which makes free text cheaper than any literal. With one, each character
+/// matched by `.` or outside of a negated class contributes its probability.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Background {
+    weights: BTreeMap<char, f64>,
+    /// Weight added to every character in the table, and once to those not in it
+    smoothing: f64,
+    total: f64,
+}
+
+impl Background {
+    /// Background from the weights of characters, i.e. counts or probabilities
+    ///
+    /// Weights are smoothed by adding the smallest positive weight to every
+    /// character, plus once for all characters that are not in the table,
+    /// which share it equally.
+    pub fn from_weights<I: IntoIterator<Item = (char, f64)>>(weights: I) -> Background {
+        let weights: BTreeMap<char, f64> = weights.into_iter().collect();
+        let smoothing = weights
+            .values()
+            .copied()
+            .filter(|w| *w > 0.0)
+            .reduce(f64::min)
+            .unwrap_or(1.0);
+        let total: f64 = weights.values().sum::<f64>() + smoothing * (weights.len() + 1) as f64;
+        Background {
+            weights,
+            smoothing,
+            total,
+        }
+    }
+
+    /// Background estimated from the character counts of a corpus
+    pub fn estimate<S: AsRef<str>>(corpus: &[S]) -> Background {
+        Background::from_weights(count_chars(corpus).into_iter().map(|(c, n)| (c, n as f64)))
+    }
+
+    /// Probability of character c, which sums to one over all characters
+    pub fn p(&self, c: char) -> f64 {
+        match self.weights.get(&c) {
+            Some(w) => (w + self.smoothing) / self.total,
+            None => self.smoothing / self.total / (N_CHARS - self.weights.len()) as f64,
+        }
+    }
+
+    /// Let the `.` and negated classes without a distribution in nfa draw from the background
+    pub fn apply(&self, nfa: &mut [State]) {
+        for state in nfa.iter_mut() {
+            if let (Kind::Dot | Kind::Class(true, _), None) = (&state.kind, &state.dist) {
+                state.dist = Some(DistLink::Indexed(Dist::Background(self.clone())));
+            }
+        }
+    }
+
+    /// Character of the table that is not one of excluded, drawn by probability
+    pub fn draw<R: Rng + ?Sized>(&self, excluded: &[char], rng: &mut R) -> Option<char> {
+        let (chars, probs): (Vec<char>, Vec<f64>) = self
+            .weights
+            .iter()
+            .filter(|(c, _)| !excluded.contains(c))
+            .map(|(c, w)| (c, w + self.smoothing))
+            .unzip();
+        let index = WeightedIndex::new(probs).ok()?;
+        Some(chars[index.sample(rng)])
+    }
+}
+
+/// Number of times each character occurs in the corpus
+pub fn count_chars<S: AsRef<str>>(corpus: &[S]) -> BTreeMap<char, u64> {
+    let mut counts = BTreeMap::new();
+    for line in corpus {
+        for c in line.as_ref().chars() {
+            *counts.entry(c).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Character as written in a frequency table, with tab, newline and backslash escaped
+pub fn escape(c: char) -> String {
+    match c {
+        '\t' => "\\t".to_string(),
+        '\n' => "\\n".to_string(),
+        '\\' => "\\\\".to_string(),
+        c => c.to_string(),
+    }
+}
+
+fn unescape(s: &str) -> Option<char> {
+    match s {
+        "\\t" => Some('\t'),
+        "\\n" => Some('\n'),
+        "\\\\" => Some('\\'),
+        s => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Parse a frequency table, with a character and its weight on each line, separated by a tab
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = Vec::new();
+        for (i, line) in s.lines().enumerate().filter(|(_, l)| !l.is_empty()) {
+            let (c, weight) = line
+                .rsplit_once('\t')
+                .ok_or(format!("line {}: expected a character and a weight", i + 1))?;
+            let c = unescape(c).ok_or(format!("line {}: {:?} is not a character", i + 1, c))?;
+            let weight: f64 = weight
+                .trim()
+                .parse()
+                .map_err(|_| format!("line {}: {} is not a number", i + 1, weight))?;
+            if !(weight >= 0.0 && weight.is_finite()) {
+                return Err(format!("line {}: weight must be non-negative", i + 1));
+            }
+            weights.push((c, weight));
+        }
+        Ok(Background::from_weights(weights))
+    }
+}
+
+/// Frequency table of the weights before smoothing, which parses back to the same background
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (c, w) in self.weights.iter() {
+            writeln!(f, "{}\t{}", escape(*c), w)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compile;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_background_from_weights() {
+        // add one to a, b and the unseen characters, which share theirs
+        let background = Background::from_weights([('a', 3.0), ('b', 1.0)]);
+        assert_relative_eq!(background.p('a'), 4.0 / 7.0);
+        assert_relative_eq!(background.p('b'), 2.0 / 7.0);
+        assert_relative_eq!(background.p('z'), 1.0 / 7.0 / (N_CHARS - 2) as f64);
+
+        // probabilities smooth by the rarest one
+        let background = Background::from_weights([('a', 0.75), ('b', 0.25)]);
+        assert_relative_eq!(background.p('a'), 1.0 / 1.75);
+        assert_relative_eq!(background.p('z'), 0.25 / 1.75 / (N_CHARS - 2) as f64);
+    }
+
+    #[test]
+    fn test_background_sums_to_one() {
+        let background = Background::from_weights([('a', 3.0), ('b', 1.0), ('\n', 0.5)]);
+        let chars: Vec<char> = (0..=char::MAX as u32).filter_map(char::from_u32).collect();
+        assert_eq!(chars.len(), N_CHARS);
+        let mass: f64 = chars.into_iter().map(|c| background.p(c)).sum();
+        assert_relative_eq!(mass, 1.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_background_estimate() {
+        let background = Background::estimate(&["aab", "a"]);
+        assert_eq!(
+            background,
+            Background::from_weights([('a', 3.0), ('b', 1.0)])
+        );
+        assert_eq!(count_chars(&["aab", "a"]), [('a', 3), ('b', 1)].into());
+    }
+
+    #[test]
+    fn test_background_from_str() {
+        let background: Background = "a\t3\n\\t\t1\n \t1\n".parse().unwrap();
+        assert_eq!(
+            background,
+            Background::from_weights([('a', 3.0), ('\t', 1.0), (' ', 1.0)])
+        );
+        assert_eq!(background.to_string().parse(), Ok(background));
+
+        assert!("ab\t1".parse::<Background>().is_err());
+        assert!("a 1".parse::<Background>().is_err());
+        assert!("a\tx".parse::<Background>().is_err());
+        assert!("a\t-1".parse::<Background>().is_err());
+    }
+
+    #[test]
+    fn test_background_apply() {
+        let mut nfa = compile("a.[^b][^c~Cat(.=1.0)][d]").unwrap();
+        let background = Background::from_weights([('a', 1.0)]);
+        background.apply(&mut nfa);
+        let is_background: Vec<bool> = nfa
+            .iter()
+            .map(|state| matches!(state.dist, Some(DistLink::Indexed(Dist::Background(_)))))
+            .collect();
+        assert_eq!(
+            is_background,
+            vec![false, false, true, true, false, false, false]
+        );
+    }
+
+    #[test]
+    fn test_background_apply_case_insensitive() {
+        // under (?i) a negated class without a distribution excludes both cases, it is never folded
+        let mut nfa = compile("(?i)a.[^b]").unwrap();
+        assert_eq!(nfa[3].kind, Kind::Class(true, vec!['B', 'b']));
+        let background = Background::from_weights([('a', 1.0)]);
+        background.apply(&mut nfa);
+        let is_background: Vec<bool> = nfa
+            .iter()
+            .map(|state| matches!(state.dist, Some(DistLink::Indexed(Dist::Background(_)))))
+            .collect();
+        assert_eq!(is_background, vec![false, false, true, true, false]);
+    }
+
+    #[test]
+    fn test_background_draw() {
+        use rand::{rngs::StdRng, SeedableRng};
+        let background = Background::from_weights([('a', 1.0), ('b', 1.0)]);
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(background.draw(&['a'], &mut rng), Some('b'));
+        assert_eq!(background.draw(&['a', 'b'], &mut rng), None);
+    }
+}
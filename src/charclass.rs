@@ -1,28 +1,263 @@
+use itertools::Itertools;
 use pest::iterators::Pair;
 
+use crate::error::CompileError;
 use crate::parser::Rule;
+use regex_syntax::hir::{Class, HirKind};
 
-pub fn build_chars(pair: Pair<Rule>) -> Vec<char> {
+/// Build the members of a character class
+///
+/// Members are ordered as they appear in the class, with ranges expanded
+/// in code point order. Repeated members keep their first position, so
+/// that index based distributions over the class are well defined.
+pub fn build_chars(pair: Pair<Rule>) -> Result<Vec<char>, CompileError> {
     match pair.as_rule() {
         Rule::PosixClass | Rule::ShortClass => {
-            let name = pair.as_str();
-            let chars = match name {
-                "[:digit:]" | "\\d" => vec!['0', '1', '2', '3', '4', '5', '6', '7', '8', '9'],
-                "[:space:]" | "\\s" => vec![' ', '\t', '\n', '\r', '\x0c', '\x0b'],
-                _ => panic!("Unknown character class {}", name),
-            };
-            chars
+            let (is_negate, chars) = build_named(pair)?;
+            // within a class the complement has to be listed, as members are united
+            match is_negate {
+                true => Ok(complement(chars)),
+                false => Ok(chars),
+            }
+        }
+        Rule::PropertyClass => {
+            let is_negate = pair.as_str().starts_with("\\P");
+            // within a class the complement has to be listed, as members are united
+            property_members(pair.into_inner().next().unwrap(), !is_negate)
+        }
+        Rule::ClassRange => {
+            let span = pair.as_span().into();
+            let (start, end) = pair
+                .into_inner()
+                .map(build_literal)
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .next_tuple()
+                .unwrap();
+            if start > end {
+                return Err(CompileError::InvalidClass(
+                    span,
+                    format!("range {}-{} is out of order", start, end),
+                ));
+            }
+            Ok((start..=end).collect())
         }
         Rule::CharacterClass => {
             let pairs = pair.into_inner();
-            let chars: Vec<char> = pairs
-                .flat_map(|p| match p.as_rule() {
-                    Rule::PosixClass | Rule::ShortClass => build_chars(p),
-                    _ => p.as_str().chars().collect(),
-                })
-                .collect();
-            chars
+            let mut chars: Vec<char> = Vec::new();
+            for p in pairs {
+                match p.as_rule() {
+                    Rule::PosixClass
+                    | Rule::ShortClass
+                    | Rule::PropertyClass
+                    | Rule::ClassRange => chars.extend(build_chars(p)?),
+                    _ => chars.push(build_literal(p)?),
+                }
+            }
+            Ok(chars.into_iter().unique().collect())
+        }
+        _ => Ok(vec![]),
+    }
+}
+
+/// Build a character class, as the characters it excludes if it unites a negated part
+///
+/// Listing the members of `[\Wa]` takes most of Unicode, which only a distribution
+/// over them needs, see build_chars. Returns whether the characters are excluded,
+/// with the characters in code point order if they are.
+pub fn build_class(pair: Pair<Rule>) -> Result<(bool, Vec<char>), CompileError> {
+    let mut members: Vec<char> = Vec::new();
+    let mut excluded: Option<Vec<char>> = None;
+    for p in pair.into_inner() {
+        let negated = match p.as_rule() {
+            Rule::PosixClass | Rule::ShortClass => match build_named(p.clone())? {
+                (true, chars) => Some(chars),
+                (false, _) => None,
+            },
+            Rule::PropertyClass => match build_property(p.clone())? {
+                (true, chars) => Some(chars),
+                (false, _) => None,
+            },
+            _ => None,
+        };
+        match (negated, p.as_rule()) {
+            // outside of every negated part is excluded from none of them
+            (Some(mut chars), _) => {
+                chars.sort_unstable();
+                excluded = Some(match excluded {
+                    Some(e) => e
+                        .into_iter()
+                        .filter(|c| chars.binary_search(c).is_ok())
+                        .collect(),
+                    None => chars,
+                });
+            }
+            (
+                None,
+                Rule::PosixClass | Rule::ShortClass | Rule::PropertyClass | Rule::ClassRange,
+            ) => members.extend(build_chars(p)?),
+            (None, _) => members.push(build_literal(p)?),
+        }
+    }
+    match excluded {
+        Some(excluded) => {
+            members.sort_unstable();
+            Ok((
+                true,
+                excluded
+                    .into_iter()
+                    .filter(|c| members.binary_search(c).is_err())
+                    .dedup()
+                    .collect(),
+            ))
+        }
+        None => Ok((false, members.into_iter().unique().collect())),
+    }
+}
+
+/// Build the members of a shorthand or POSIX class, i.e. `\w` or `[:alpha:]`
+///
+/// Members are ASCII and listed in code point order, so that index based
+/// distributions over them are well defined. Only `\s` and `[:space:]` list
+/// the space first, as they always have.
+///
+/// | class                | members                          |
+/// |----------------------|----------------------------------|
+/// | `\d`, `[:digit:]`    | `0-9`                            |
+/// | `\w`, `[:word:]`     | `0-9`, `A-Z`, `_`, `a-z`         |
+/// | `\s`, `[:space:]`    | space, `\t \n \r \f \v`          |
+/// | `[:alpha:]`          | `A-Z`, `a-z`                     |
+/// | `[:alnum:]`          | `0-9`, `A-Z`, `a-z`              |
+/// | `[:upper:]`          | `A-Z`                            |
+/// | `[:lower:]`          | `a-z`                            |
+/// | `[:xdigit:]`         | `0-9`, `A-F`, `a-f`              |
+/// | `[:punct:]`          | ASCII punctuation from `!` to `~` |
+/// | `[:blank:]`          | `\t`, space                      |
+/// | `[:cntrl:]`          | `\x00-\x1F`, `\x7F`              |
+/// | `[:graph:]`          | `!` to `~`                       |
+/// | `[:print:]`          | space to `~`                     |
+/// | `[:ascii:]`          | `\x00-\x7F`                      |
+///
+/// Returns whether the class is negated, as `\W \S \D` and `[:^alpha:]`
+/// are, with the members of the class it excludes.
+pub fn build_named(pair: Pair<Rule>) -> Result<(bool, Vec<char>), CompileError> {
+    let name = pair.as_str();
+    let (is_negate, base) = match name.strip_prefix("[:^") {
+        Some(rest) => (true, format!("[:{}", rest)),
+        None => match name {
+            "\\W" | "\\S" | "\\D" => (true, name.to_lowercase()),
+            _ => (false, name.to_string()),
+        },
+    };
+    let chars: Vec<char> = match base.as_str() {
+        "\\d" | "[:digit:]" => ('0'..='9').collect(),
+        "\\w" | "[:word:]" => ('0'..='9')
+            .chain('A'..='Z')
+            .chain(['_'])
+            .chain('a'..='z')
+            .collect(),
+        "\\s" | "[:space:]" => vec![' ', '\t', '\n', '\r', '\x0c', '\x0b'],
+        "[:alpha:]" => ('A'..='Z').chain('a'..='z').collect(),
+        "[:alnum:]" => ('0'..='9').chain('A'..='Z').chain('a'..='z').collect(),
+        "[:upper:]" => ('A'..='Z').collect(),
+        "[:lower:]" => ('a'..='z').collect(),
+        "[:xdigit:]" => ('0'..='9').chain('A'..='F').chain('a'..='f').collect(),
+        "[:punct:]" => ('!'..='~').filter(|c| c.is_ascii_punctuation()).collect(),
+        "[:blank:]" => vec!['\t', ' '],
+        "[:cntrl:]" => ('\0'..='\x7f').filter(|c| c.is_ascii_control()).collect(),
+        "[:graph:]" => ('!'..='~').collect(),
+        "[:print:]" => (' '..='~').collect(),
+        "[:ascii:]" => ('\0'..='\x7f').collect(),
+        _ => {
+            return Err(CompileError::UnknownClass(
+                pair.as_span().into(),
+                name.to_string(),
+            ))
         }
-        _ => vec![],
+    };
+    Ok((is_negate, chars))
+}
+
+/// Build the character of a literal, decoding escapes like `\t` or `\u{e9}`
+pub fn build_literal(pair: Pair<Rule>) -> Result<char, CompileError> {
+    if pair.as_rule() != Rule::EscapedCode {
+        return Ok(pair.as_str().chars().next().unwrap());
+    }
+    let span = pair.as_span().into();
+    let code = pair.into_inner().next().unwrap();
+    let c = match code.as_rule() {
+        Rule::ControlCode => match code.as_str() {
+            "t" => Some('\t'),
+            "n" => Some('\n'),
+            "r" => Some('\r'),
+            "f" => Some('\x0c'),
+            "v" => Some('\x0b'),
+            _ => Some('\0'),
+        },
+        // both are hexadecimal, at most 6 digits fit in u32
+        _ => u32::from_str_radix(code.as_str(), 16)
+            .ok()
+            .and_then(char::from_u32),
+    };
+    c.ok_or_else(|| {
+        CompileError::InvalidEscape(
+            span,
+            format!("{} is not a Unicode scalar value", code.as_str()),
+        )
+    })
+}
+
+/// Index of the character among the members, up to case for a folded class
+///
+/// An exact match comes first, so members that only differ in case keep their own index.
+pub fn member_index(members: &[char], c: char, is_folded: bool) -> Option<usize> {
+    let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+    members
+        .iter()
+        .position(|&m| m == c)
+        .or_else(|| match is_folded {
+            true => members.iter().position(|&m| fold(m) == fold(c)),
+            false => None,
+        })
+}
+
+/// Characters outside of the members, in code point order
+fn complement(mut chars: Vec<char>) -> Vec<char> {
+    chars.sort_unstable();
+    (char::MIN..=char::MAX)
+        .filter(|c| chars.binary_search(c).is_err())
+        .collect()
+}
+
+/// Build the members of a Unicode property class, i.e. `\p{L}` or `\p{Nd}`
+///
+/// Members are in code point order. Returns whether the class is negated
+/// as `\P{..}` is, with the members of the property it excludes.
+pub fn build_property(pair: Pair<Rule>) -> Result<(bool, Vec<char>), CompileError> {
+    let is_negate = pair.as_str().starts_with("\\P");
+    let chars = property_members(pair.into_inner().next().unwrap(), true)?;
+    Ok((is_negate, chars))
+}
+
+/// Code points with, or else without, the property by name, in code point order
+fn property_members(name_pair: Pair<Rule>, has_property: bool) -> Result<Vec<char>, CompileError> {
+    let name = name_pair.as_str();
+    let source = match has_property {
+        true => format!("\\p{{{}}}", name),
+        false => format!("\\P{{{}}}", name),
+    };
+    // the property tables are those of the regex crate
+    match regex_syntax::parse(&source).map(|hir| hir.into_kind()) {
+        Ok(HirKind::Class(Class::Unicode(class))) => Ok(class
+            .ranges()
+            .iter()
+            .flat_map(|range| range.start()..=range.end())
+            .collect()),
+        // a property of a single code point is a literal
+        Ok(HirKind::Literal(literal)) => Ok(String::from_utf8_lossy(&literal.0).chars().collect()),
+        _ => Err(CompileError::UnknownClass(
+            name_pair.as_span().into(),
+            name.to_string(),
+        )),
     }
 }
@@ -1,8 +1,15 @@
-use {clap::Parser, std::path::PathBuf};
+use clap::{Args, Parser, Subcommand};
+use pregex::Semiring;
+use std::f64::consts::LN_2;
+use std::str::FromStr;
 
 #[derive(Parser, Debug, Clone)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Config {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     /// Verbosity
     #[clap(short, parse(from_occurrences))]
     pub verbosity: usize,
@@ -15,9 +22,33 @@ pub struct Config {
     #[clap(short = 'z', action)]
     pub visualize: bool,
 
+    /// Merge competing paths by their maximum (best path) or sum (marginal likelihood)
+    #[clap(short, long, default_value = "max", value_name = "max|sum")]
+    pub semiring: Semiring,
+
+    /// Print the probability, log-likelihood, bits of surprisal or per-character perplexity
+    #[clap(long, default_value = "prob", value_name = "prob|log|bits|ppl")]
+    pub score: Score,
+
+    /// Print the most likely path through the pattern for each matching line
+    #[clap(long, action)]
+    pub explain_path: bool,
+
+    /// Print the text captured by each group on the most likely path, and its share of the log-likelihood
+    #[clap(long, action)]
+    pub captures: bool,
+
+    /// Print the byte offsets and score of every match inside each line, instead of scoring the line
+    #[clap(long, action)]
+    pub find: bool,
+
+    /// Score `.` and negated classes by a character frequency table, as written by `pregex freqs`
+    #[clap(long, value_name = "FILE")]
+    pub background: Option<String>,
+
     /// Regex pattern
     #[clap(required = true)]
-    pub pattern: String,
+    pub pattern: Option<String>,
 
     /// String to match
     #[clap(
@@ -27,3 +58,114 @@ pub struct Config {
     )]
     pub input_string: Option<String>,
 }
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Draw random strings from the pattern
+    Sample(SampleConfig),
+    /// Fit the distribution parameters of the pattern to a corpus, one example per line
+    Fit(FitConfig),
+    /// Count the characters of a corpus, for use as a background with --background
+    Freqs(FreqsConfig),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SampleConfig {
+    /// Number of strings to draw
+    #[clap(short, default_value = "1")]
+    pub n: usize,
+
+    /// Seed of the random number generator, for reproducible samples
+    #[clap(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+
+    /// Draw `.` and negated classes from a character frequency table
+    #[clap(long, value_name = "FILE")]
+    pub background: Option<String>,
+
+    /// Regex pattern
+    pub pattern: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct FitConfig {
+    /// Corpus file or - for stdin
+    #[clap(short, long, default_value = "-", value_name = "FILE")]
+    pub input_file: String,
+
+    /// Maximum number of EM iterations
+    #[clap(long, default_value = "20", value_name = "N")]
+    pub iterations: usize,
+
+    /// Regex pattern
+    pub pattern: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct FreqsConfig {
+    /// Corpus file or - for stdin
+    #[clap(short, long, default_value = "-", value_name = "FILE")]
+    pub input_file: String,
+}
+
+/// How the likelihood of a matching line is reported
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Score {
+    /// Probability, which may underflow to zero on long inputs
+    Prob,
+    /// Natural log of the probability
+    Log,
+    /// Surprisal in bits, i.e. -log2 of the probability
+    Bits,
+    /// Per-character perplexity, i.e. exp of the average negative log-likelihood
+    Perplexity,
+}
+
+impl Score {
+    /// Convert a log-likelihood of input with n_chars characters to this score
+    pub fn of_log_likelihood(&self, log_likelihood: f64, n_chars: usize) -> f64 {
+        match self {
+            Score::Prob => log_likelihood.exp(),
+            Score::Log => log_likelihood,
+            Score::Bits => -log_likelihood / LN_2,
+            Score::Perplexity => (-log_likelihood / usize::max(1, n_chars) as f64).exp(),
+        }
+    }
+}
+
+impl FromStr for Score {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prob" => Ok(Score::Prob),
+            "log" => Ok(Score::Log),
+            "bits" => Ok(Score::Bits),
+            "ppl" => Ok(Score::Perplexity),
+            _ => Err(format!(
+                "unknown score {}, expected prob, log, bits or ppl",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_score_of_log_likelihood() {
+        let ll = 0.25_f64.ln();
+        assert_eq!(Score::Log.of_log_likelihood(ll, 2), ll);
+        assert_eq!(Score::Bits.of_log_likelihood(ll, 2), 2.0);
+        assert!((Score::Perplexity.of_log_likelihood(ll, 2) - 2.0).abs() < 1e-12);
+        assert!((Score::Prob.of_log_likelihood(ll, 2) - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_score_from_str() {
+        assert_eq!("ppl".parse(), Ok(Score::Perplexity));
+        assert!("nats".parse::<Score>().is_err());
+    }
+}
@@ -1,25 +1,40 @@
 #![allow(dead_code, unused_variables)]
-use crate::ast::{AstNode, Kind};
-use crate::nfa::State;
+use crate::ast::Kind;
+use crate::background::Background;
+use crate::error::{CompileError, Span};
 use crate::parser::Rule;
 use crate::regex_state::Token;
-use itertools::Itertools;
+use crate::semiring::{one, scale};
 
 use pest::iterators::Pair;
-use statrs::distribution::{Bernoulli, Binomial, Categorical, Discrete, Geometric};
-use statrs::statistics::Distribution;
-use std::collections::{HashMap, HashSet};
+use statrs::distribution::{
+    Bernoulli, Binomial, Categorical, ContinuousCDF, Discrete, DiscreteCDF, Geometric, Laplace,
+    NegativeBinomial, Normal, Poisson,
+};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 
+/// Rounding allowed when the probabilities written in a pattern are summed
+const MASS_TOLERANCE: f64 = 1e-9;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Dist {
-    Categorical(Vec<f64>),     // p[]
-    Constant(u64, u64, f64),   // n_min, n_max, p
-    ExactlyTimes(u64),         // n_match
-    PGeometric(u64, u64, f64), // n_min, n_max, p
-    PBinomial(u64, u64, f64),  // n_min, n_max, p
-    PBernoulli(u64, u64, f64), // n_min, n_max, p
-    PZipf(u64, u64, f64),      // n_min, n_max, s
+    Categorical(Vec<f64>),            // p[]
+    Constant(u64, u64, f64),          // n_min, n_max, p
+    ExactlyTimes(u64),                // n_match
+    PGeometric(u64, u64, f64),        // n_min, n_max, p
+    PBinomial(u64, u64, f64),         // n_min, n_max, p
+    PBernoulli(u64, u64, f64),        // n_min, n_max, p
+    PZipf(u64, u64, f64),             // n_min, n_max, s
+    PZeta(u64, u64, f64),             // n_min, n_max, s
+    PPoisson(u64, u64, f64),          // n_min, n_max, lambda
+    PNegBinomial(u64, u64, f64, f64), // n_min, n_max, r, p
+    PNormal(u64, u64, f64, f64),      // n_min, n_max, mu, sigma
+    PLaplace(u64, u64, f64, f64),     // n_min, n_max, mu, b
+    Mixture(Vec<(f64, Dist)>),        // (weight, component)[]
+    Histogram(BTreeMap<u64, f64>),    // p of each count
+    Background(Background),           // p of each character
 }
 
 impl fmt::Display for Dist {
@@ -32,6 +47,24 @@ impl fmt::Display for Dist {
             Dist::PBinomial(_, _, p) => write!(f, "~Bin({})", p),
             Dist::PBernoulli(_, _, p) => write!(f, "~Ber({})", p),
             Dist::PZipf(_, _, p) => write!(f, "~Zipf({})", p),
+            Dist::PZeta(_, _, s) => write!(f, "~Zeta({})", s),
+            Dist::PPoisson(_, _, lambda) => write!(f, "~Pois({})", lambda),
+            Dist::PNegBinomial(_, _, r, p) => write!(f, "~NegBin({},{})", r, p),
+            Dist::PNormal(_, _, _, sigma) => write!(f, "~Norm({})", sigma),
+            Dist::PLaplace(_, _, _, b) => write!(f, "~Laplace({})", b),
+            Dist::Mixture(components) => {
+                let components: Vec<String> = components
+                    .iter()
+                    .map(|(w, d)| format!("{}*{}", w, d.to_string().trim_start_matches('~')))
+                    .collect();
+                write!(f, "~Mix({})", components.join(","))
+            }
+            Dist::Histogram(counts) => {
+                let counts: Vec<String> =
+                    counts.iter().map(|(n, p)| format!("{}={}", n, p)).collect();
+                write!(f, "~Hist({})", counts.join(","))
+            }
+            Dist::Background(_) => write!(f, ""),
         }
     }
 }
@@ -40,80 +73,242 @@ impl Dist {
     pub fn default_from(quantifier_kind: &Kind) -> Option<Self> {
         match quantifier_kind {
             Kind::ExactQuantifier(n) => Some(Dist::ExactlyTimes(*n)),
+            Kind::RangeQuantifier(n_min, n_max) => Some(Dist::Constant(*n_min, *n_max, 1.0)),
             _ => None,
         }
     }
 
     /// Distribution from kind and distribution params
     ///
-    /// Eg. complete_from(ExactQuantifier(2), Dist::Normal(sigma))
-    /// would return a Normal distribution centered at 2.
-    pub fn complete_from(kind: &Kind, dist_pair: Pair<'_, crate::parser::Rule>) -> Self {
-        let n = match kind {
-            Kind::ExactQuantifier(n) => *n,
-            _ => 0, // required n is zero
+    /// Eg. complete_from(ExactQuantifier(2), `~Norm(sigma)`)
+    /// returns a discretized Normal distribution centered at 2.
+    pub fn complete_from(
+        kind: &Kind,
+        dist_pair: Pair<'_, crate::parser::Rule>,
+    ) -> Result<Self, CompileError> {
+        let (n, n_max) = match kind {
+            Kind::ExactQuantifier(n) => (*n, None),
+            Kind::RangeQuantifier(n_min, n_max) => (*n_min, Some(*n_max)),
+            // short quantifiers are ranges, i.e. {0,1}, {1,} and {0,}
+            Kind::Quantifier('?') => (0, Some(1)),
+            Kind::Quantifier('+') => (1, Some(u64::MAX)),
+            Kind::Quantifier(_) => (0, Some(u64::MAX)),
+            _ => (0, None), // required n is zero
         };
         let (is_negate, c) = match kind {
             Kind::Class(neg, c) => (*neg, Some(c)),
             _ => (false, None),
         };
+        let n_branches = match kind {
+            Kind::Alternation(_, _, _) => Some(count_branches(kind)),
+            _ => None,
+        };
 
+        let dist_span: Span = dist_pair.as_span().into();
         let mut pair = dist_pair.into_inner();
-        let name = pair.next().unwrap().as_span().as_str().to_lowercase();
+        let name_pair = pair.next().unwrap();
+        let name = name_pair.as_str().to_lowercase();
 
         // Parse parameters, that may be supplied in various formats
-        let (params, params_named): (Vec<Option<&str>>, Vec<_>) = pair
-            .map(|p| match p.as_rule() {
+        let mut params: Vec<(Span, f64)> = Vec::new();
+        let mut params_named: HashMap<char, (Span, f64)> = HashMap::new();
+        let mut components: Vec<(Span, f64, Dist)> = Vec::new();
+        let mut counts: Vec<(Span, u64, f64)> = Vec::new();
+        for p in pair {
+            let span: Span = p.as_span().into();
+            match p.as_rule() {
                 // indexed form, i.e. (0.0, 0.1, 0.2, ...)
-                Rule::IndexParam => (Some(p.as_str()), None),
+                Rule::IndexParam => params.push((span, parse_param(span, p.as_str())?)),
                 // named form, i.e. (a=0.0, b=0.1, c=0.2, ...)
                 Rule::NamedParam => {
                     let p_str = p.as_str();
                     let (key, val) = p_str.trim().split_at(p_str.find('=').unwrap());
-                    (None, Some((key, &val[1..])))
+                    let val = parse_param(span, &val[1..])?;
+                    if name == "hist" {
+                        counts.push((span, parse_count(span, key)?, val));
+                        continue;
+                    }
+                    let mut chars = key.chars();
+                    let key = match (chars.next(), chars.next()) {
+                        (Some(c), None) => c,
+                        _ => {
+                            return Err(CompileError::InvalidParameter(
+                                span,
+                                format!("{} is not a single character", key),
+                            ))
+                        }
+                    };
+                    params_named.insert(key, (span, val));
+                }
+                // file form, i.e. (@lengths.tsv)
+                Rule::FileParam if name == "hist" => {
+                    counts.extend(read_counts(span, &p.as_str()[1..])?)
+                }
+                Rule::FileParam => {
+                    return Err(CompileError::InvalidDistribution(
+                        span,
+                        format!("{} does not read from a file", name_pair.as_str()),
+                    ))
+                }
+                // weighted form, i.e. (0.2*Geo(0.5), 0.8*Pois(3.0))
+                Rule::MixComponent => {
+                    let mut inner = p.into_inner();
+                    let weight = inner.next().unwrap();
+                    let weight = parse_param(weight.as_span().into(), weight.as_str())?;
+                    let component = Dist::complete_from(kind, inner.next().unwrap())?;
+                    components.push((span, weight, component));
                 }
                 _ => unreachable!(),
-            })
-            .unzip();
-        let params: Vec<&str> = params.into_iter().flatten().collect();
-        let params_named: HashMap<&str, &str> = params_named.into_iter().flatten().collect();
+            }
+        }
+
+        // Check the parameter form matches the distribution
+        if n_branches.is_some() && name != "cat" {
+            return Err(CompileError::InvalidDistribution(
+                dist_span,
+                format!("{} cannot weigh alternatives, use Cat", name_pair.as_str()),
+            ));
+        }
+        if (name == "mix") == components.is_empty() {
+            return Err(CompileError::InvalidDistribution(
+                dist_span,
+                match name.as_str() {
+                    "mix" => "Mix takes weighted distributions, i.e. Mix(0.5*Geo,0.5*Pois(3.0))"
+                        .to_string(),
+                    _ => format!("{} takes no weighted distributions", name_pair.as_str()),
+                },
+            ));
+        }
+        let arity = match name.as_str() {
+            "cat" => n_branches.unwrap_or(0),
+            "const" | "geo" | "ber" | "bin" | "zipf" | "zeta" | "pois" | "norm" | "laplace"
+            | "dir" => 1,
+            "negbin" => 2,
+            "mix" | "hist" => 0,
+            _ => {
+                return Err(CompileError::UnknownDistribution(
+                    name_pair.as_span().into(),
+                    name_pair.as_str().to_string(),
+                ))
+            }
+        };
+        if name == "cat" && c.is_some() && !params.is_empty() {
+            return Err(CompileError::InvalidDistribution(
+                params[0].0,
+                "Cat weighs the members of a class by name, i.e. [ab~Cat(a=0.5,b=0.5)]".to_string(),
+            ));
+        }
+        if params.len() > arity {
+            return Err(CompileError::InvalidDistribution(
+                params[arity].0,
+                format!(
+                    "{} takes at most {} parameter(s)",
+                    name_pair.as_str(),
+                    arity
+                ),
+            ));
+        }
+        if (!matches!(name.as_str(), "cat" | "dir") || n_branches.is_some())
+            && !params_named.is_empty()
+        {
+            return Err(CompileError::InvalidDistribution(
+                dist_span,
+                format!("{} takes no named parameters", name_pair.as_str()),
+            ));
+        }
+        let param = |default: f64| params.first().map_or(default, |(_, p)| *p);
+        let param_span = params.first().map_or(dist_span, |(span, _)| *span);
 
         // Instantiate distribution with possible default parameters
-        match name.as_str() {
+        let dist = match name.as_str() {
             "const" => {
-                let p: f64 = params.first().unwrap_or(&"1.0").parse().unwrap();
-                Dist::Constant(n, n, p)
+                let p = param(1.0);
+                ensure((0.0..=1.0).contains(&p), param_span, "p must be in [0, 1]")?;
+                Dist::Constant(n, n_max.unwrap_or(n), p)
             }
             "geo" => {
-                let p: f64 = params.first().unwrap_or(&"0.5").parse().unwrap();
-                Dist::PGeometric(n, u64::MAX, p)
+                let p = param(0.5);
+                ensure(p > 0.0 && p <= 1.0, param_span, "p must be in (0, 1]")?;
+                Dist::PGeometric(n, n_max.unwrap_or(u64::MAX), p)
             }
             "ber" => {
-                let p: f64 = params.first().unwrap_or(&"1.0").parse().unwrap();
-                Dist::PBernoulli(0, 2, p)
+                let p = param(1.0);
+                ensure((0.0..=1.0).contains(&p), param_span, "p must be in [0, 1]")?;
+                match n_max {
+                    Some(n_max) => Dist::PBernoulli(n, n_max, p),
+                    None => Dist::PBernoulli(0, 2, p),
+                }
             }
             "bin" => {
-                let p = params.first().unwrap_or(&"1.0").parse::<f64>().unwrap();
-                let n = match c {
+                let p = param(1.0);
+                ensure((0.0..=1.0).contains(&p), param_span, "p must be in [0, 1]")?;
+                let (n_min, n_max) = match c {
                     Some(c) => match c.len() {
-                        0 => 0,
+                        0 => (0, 0),
                         // binomial distribution has support for x >= 0
-                        n => (n - 1) as u64,
+                        n => (0, (n - 1) as u64),
                     },
-                    None => n,
+                    None => (n_max.map_or(0, |_| n), n_max.unwrap_or(n)),
                 };
-                Dist::PBinomial(0, n, p)
+                ensure_bounded(n_max, dist_span, "Bin")?;
+                Dist::PBinomial(n_min, n_max, p)
+            }
+            "cat" if n_branches.is_some() => {
+                let n_branches = n_branches.unwrap();
+                for (span, p) in params.iter() {
+                    ensure((0.0..=1.0).contains(p), *span, "p must be in [0, 1]")?;
+                }
+                let explicit_mass = params.iter().map(|(_, p)| p).sum::<f64>();
+                ensure(
+                    explicit_mass <= 1.0 + MASS_TOLERANCE,
+                    dist_span,
+                    "probabilities must not sum to more than 1",
+                )?;
+
+                // Branches without a weight share the rest of the mass equally
+                let n_implicit = n_branches - params.len();
+                ensure(
+                    n_implicit == 0 || 1.0 - explicit_mass > MASS_TOLERANCE,
+                    dist_span,
+                    "no probability is left for the branches without a weight",
+                )?;
+                let p_implicit =
+                    f64::max(0.0, 1.0 - explicit_mass) / usize::max(1, n_implicit) as f64;
+                let mut prob_mass: Vec<f64> = params.iter().map(|(_, p)| *p).collect();
+                prob_mass.resize(n_branches, p_implicit);
+
+                // Insert the mass no branch takes first, like the remainder of a class
+                let p_remainder = f64::max(0.0, 1.0 - prob_mass.iter().sum::<f64>());
+                prob_mass.insert(0, p_remainder);
+                Dist::Categorical(prob_mass)
             }
             "cat" => {
-                let params_named: HashMap<char, f64> = params_named
-                    .into_iter()
-                    .map(|(k, v)| (k.chars().next().unwrap(), v.parse().unwrap()))
-                    .collect();
-                let n_explicit = params_named.iter().filter(|&(k, _)| *k != '.').count();
-                let n_implicit = match c {
-                    Some(chars) => usize::max(1, chars.len() - n_explicit),
-                    None => 1,
+                let c = match c {
+                    Some(c) => c,
+                    None => {
+                        return Err(CompileError::InvalidDistribution(
+                            dist_span,
+                            "Cat is only valid for character classes and alternatives".to_string(),
+                        ))
+                    }
                 };
+                for (k, (span, v)) in params_named.iter() {
+                    ensure((0.0..=1.0).contains(v), *span, "p must be in [0, 1]")?;
+                    ensure(
+                        *k == '.' || c.contains(k),
+                        *span,
+                        format!("{} is not a member of the class", k),
+                    )?;
+                }
+                let params_named: HashMap<char, f64> =
+                    params_named.into_iter().map(|(k, (_, v))| (k, v)).collect();
+                ensure(
+                    params_named.values().sum::<f64>() <= 1.0 + MASS_TOLERANCE,
+                    dist_span,
+                    "probabilities must not sum to more than 1",
+                )?;
+                let n_explicit = params_named.iter().filter(|&(k, _)| *k != '.').count();
+                let n_implicit = usize::max(1, c.len() - n_explicit);
 
                 let prob_mass = match is_negate {
                     true => {
@@ -137,7 +332,6 @@ impl Dist {
                         let p_implicit = f64::max(0.0, implicit_mass) / n_implicit as f64;
 
                         let mut prob_mass: Vec<f64> = c
-                            .expect("chars to be passed")
                             .iter()
                             .map(|c| *params_named.get(c).unwrap_or(&p_implicit))
                             .collect();
@@ -154,7 +348,6 @@ impl Dist {
                         // Probability mass for valid character without given weight
                         let p_implicit = f64::max(0.0, implicit_mass) / n_implicit as f64;
                         let mut prob_mass: Vec<f64> = c
-                            .expect("chars to be passed")
                             .iter()
                             .map(|c| *params_named.get(c).unwrap_or(&p_implicit))
                             .collect();
@@ -170,20 +363,204 @@ impl Dist {
                         prob_mass
                     }
                 };
+                ensure(
+                    prob_mass.iter().sum::<f64>() > 0.0,
+                    dist_span,
+                    "probabilities must not all be zero",
+                )?;
                 Dist::Categorical(prob_mass)
             }
-            "zipf" => {
-                let p: f64 = params.first().unwrap_or(&"1.0").parse().unwrap();
-                let n = match c {
-                    Some(c) => c.len() as u64,
-                    None => n,
+            "pois" => {
+                let lambda = param(1.0);
+                ensure(
+                    lambda > 0.0 && lambda.is_finite(),
+                    param_span,
+                    "lambda must be positive",
+                )?;
+                Dist::PPoisson(n, n_max.unwrap_or(u64::MAX), lambda)
+            }
+            "negbin" => {
+                let r = param(1.0);
+                ensure(r > 0.0 && r.is_finite(), param_span, "r must be positive")?;
+                let (p_span, p) = params.get(1).copied().unwrap_or((dist_span, 0.5));
+                ensure(p > 0.0 && p <= 1.0, p_span, "p must be in (0, 1]")?;
+                Dist::PNegBinomial(n, n_max.unwrap_or(u64::MAX), r, p)
+            }
+            "norm" | "laplace" => {
+                let scale = param(1.0);
+                ensure(
+                    scale > 0.0 && scale.is_finite(),
+                    param_span,
+                    "scale must be positive",
+                )?;
+                // Centered on an exact count, or on the middle of a bounded range
+                let (n_min, n_max, mu) = match n_max {
+                    None => (0, u64::MAX, n as f64),
+                    Some(u64::MAX) => (n, u64::MAX, n as f64),
+                    Some(n_max) => (n, n_max, (n + n_max) as f64 / 2.0),
                 };
-                Dist::PZipf(0, n, p)
+                match name.as_str() {
+                    "norm" => Dist::PNormal(n_min, n_max, mu, scale),
+                    _ => Dist::PLaplace(n_min, n_max, mu, scale),
+                }
             }
-            _ => {
-                panic!("Unknown distribution {}", name)
+            "mix" => {
+                for (span, weight, component) in components.iter() {
+                    ensure(
+                        (0.0..=1.0).contains(weight),
+                        *span,
+                        "weight must be in [0, 1]",
+                    )?;
+                    if let Dist::Constant(_, _, _) | Dist::ExactlyTimes(_) = component {
+                        return Err(CompileError::InvalidDistribution(
+                            *span,
+                            "Const cannot be mixed".to_string(),
+                        ));
+                    }
+                }
+                let total = components.iter().map(|(_, w, _)| w).sum::<f64>();
+                ensure(
+                    (total - 1.0).abs() <= MASS_TOLERANCE,
+                    dist_span,
+                    "weights must sum to 1",
+                )?;
+                Dist::Mixture(components.into_iter().map(|(_, w, d)| (w, d)).collect())
             }
-        }
+            "hist" => {
+                if c.is_some() || n_branches.is_some() {
+                    return Err(CompileError::InvalidDistribution(
+                        dist_span,
+                        "Hist is only valid for quantifiers".to_string(),
+                    ));
+                }
+                for (span, _, w) in counts.iter() {
+                    ensure(
+                        *w >= 0.0 && w.is_finite(),
+                        *span,
+                        "weight must be non-negative",
+                    )?;
+                }
+                let total: f64 = counts.iter().map(|(_, _, w)| w).sum();
+                ensure(
+                    total > 0.0,
+                    dist_span,
+                    "Hist needs a count with positive weight",
+                )?;
+
+                // Counts outside the bounds of the quantifier are dropped, like a truncated
+                // distribution, and weights may be counts, so the rest are normalized
+                let bounds = n..=n_max.unwrap_or(u64::MAX);
+                counts.retain(|(_, n, _)| bounds.contains(n));
+                let total: f64 = counts.iter().map(|(_, _, w)| w).sum();
+                let within = match *bounds.end() {
+                    u64::MAX => format!("of at least {}", n),
+                    n_max => format!("from {} to {}", n, n_max),
+                };
+                ensure(
+                    total > 0.0,
+                    dist_span,
+                    format!("Hist needs a count {} with positive weight", within),
+                )?;
+                let mut histogram = BTreeMap::new();
+                for (_, n, w) in counts {
+                    *histogram.entry(n).or_insert(0.0) += w / total;
+                }
+                Dist::Histogram(histogram)
+            }
+            "zeta" => {
+                let s = param(2.0);
+                ensure(
+                    s > 1.0 && s.is_finite(),
+                    param_span,
+                    "s must be greater than 1",
+                )?;
+                if c.is_some() {
+                    return Err(CompileError::InvalidDistribution(
+                        dist_span,
+                        "Zeta is only valid for quantifiers, use Zipf for classes".to_string(),
+                    ));
+                }
+                // the support of Zeta starts at 1, it has no mass for leaving out the expression
+                if n == 0 {
+                    return Err(CompileError::InvalidDistribution(
+                        dist_span,
+                        "Zeta needs at least one repetition, i.e. a+~Zeta or a{1,5~Zeta}"
+                            .to_string(),
+                    ));
+                }
+                Dist::PZeta(n, n_max.unwrap_or(u64::MAX), s)
+            }
+            "dir" => {
+                let c = match c {
+                    Some(c) => c,
+                    None => {
+                        return Err(CompileError::InvalidDistribution(
+                            dist_span,
+                            "Dir is only valid for character classes".to_string(),
+                        ))
+                    }
+                };
+                let alpha = param(1.0);
+                ensure(
+                    alpha > 0.0 && alpha.is_finite(),
+                    param_span,
+                    "concentration must be positive",
+                )?;
+                for (k, (span, v)) in params_named.iter() {
+                    ensure(
+                        *v >= 0.0 && v.is_finite(),
+                        *span,
+                        "count must be non-negative",
+                    )?;
+                    ensure(
+                        *k == '.' || c.contains(k),
+                        *span,
+                        format!("{} is not a member of the class", k),
+                    )?;
+                }
+                let count = |k: &char| params_named.get(k).map_or(0.0, |(_, v)| *v);
+
+                // Every member gets the prior on top of its count, so none is left without mass,
+                // the remainder only if it is what a negated class matches
+                let mut pseudo_counts: Vec<f64> = c.iter().map(|k| count(k) + alpha).collect();
+                let remainder = count(&'.') + if is_negate { alpha } else { 0.0 };
+                pseudo_counts.insert(0, remainder);
+                let total: f64 = pseudo_counts.iter().sum();
+                Dist::Categorical(pseudo_counts.into_iter().map(|n| n / total).collect())
+            }
+            "zipf" => {
+                let s = param(1.0);
+                ensure(
+                    s >= 0.0 && s.is_finite(),
+                    param_span,
+                    "s must be non-negative",
+                )?;
+                let (n_min, n_max) = match c {
+                    Some(c) => (0, c.len() as u64),
+                    None => (n_max.map_or(0, |_| n), n_max.unwrap_or(n)),
+                };
+                ensure(n_max > 0, dist_span, "Zipf needs a support of at least 1")?;
+                ensure_bounded(n_max, dist_span, "Zipf")?;
+                Dist::PZipf(n_min, n_max, s)
+            }
+            _ => unreachable!(),
+        };
+        Ok(dist)
+    }
+
+    /// Count past which repetitions need not be told apart, and the factor each one scales p by,
+    /// if count n is past it
+    ///
+    /// Exact for a geometric or constant with no upper bound, which are memoryless, and past
+    /// the last count of a histogram, which has no mass. Other counts are all told apart.
+    pub fn saturation(&self, n: u64) -> Option<(u64, f64)> {
+        let saturation = match self {
+            Dist::PGeometric(n_min, u64::MAX, c) => Some((*n_min, 1.0 - c)),
+            Dist::Constant(n_min, u64::MAX, _) => Some((*n_min, 1.0)),
+            Dist::Histogram(histogram) => histogram.keys().next_back().map(|n_max| (*n_max, 0.0)),
+            _ => None,
+        };
+        saturation.filter(|(n_saturated, _)| n > *n_saturated)
     }
 
     /// Test helper
@@ -191,30 +568,37 @@ impl Dist {
         self.evaluate(x, log)
     }
 
-    /// Evaluate (p0, p1) for state arrows (state.outs)
+    /// Evaluate (p0, p1) for state arrows (state.outs), in linear or log space
     pub fn evaluate(&self, x: u64, log: bool) -> (f64, f64) {
         // Special distributions
         match self {
             Dist::Constant(n_min, n_max, p) => {
                 let n = x;
                 return match n >= *n_min && n <= *n_max {
-                    true => match log {
-                        true => return (p.ln(), p.ln()),
-                        false => return (*p, *p),
-                    },
-                    false => (0.0, 0.0),
+                    true => scaled((*p, *p), log),
+                    false => scaled((0.0, 0.0), log),
                 };
             }
+            Dist::Mixture(components) => {
+                let p: f64 = components
+                    .iter()
+                    .map(|(w, d)| w * d.evaluate(x, false).1)
+                    .sum();
+                return scaled((1. - p, p), log);
+            }
+            Dist::Histogram(histogram) => {
+                let p = *histogram.get(&x).unwrap_or(&0.0);
+                return scaled((1. - p, p), log);
+            }
             #[allow(clippy::comparison_chain)]
             Dist::ExactlyTimes(n_match) => {
                 let n = x;
-                // does not depend on log
                 if n == *n_match {
-                    return (0.0, 1.0);
+                    return scaled((0.0, 1.0), log);
                 } else if n < *n_match {
-                    return (1.0, 0.0);
+                    return scaled((1.0, 0.0), log);
                 } else {
-                    return (0.0, 0.0);
+                    return scaled((0.0, 0.0), log);
                 }
             }
             _ => {}
@@ -222,55 +606,153 @@ impl Dist {
 
         // Evaluate point mass function from distribution
         let p = match self {
-            Dist::PGeometric(n_min, _, c) => {
+            Dist::PGeometric(n_min, n_max, c) => {
                 if x < *n_min {
-                    return (1.0, 0.0);
+                    return scaled((1.0, 0.0), log);
                 }
-                let x = x - n_min + 1;
-                match log {
-                    true => Geometric::new(*c).unwrap().ln_pmf(x),
-                    false => Geometric::new(*c).unwrap().pmf(x),
+                if x > *n_max {
+                    return scaled((0.0, 0.0), log);
                 }
+                // Renormalize the mass of a geometric truncated at n_max
+                let mass = match *n_max {
+                    u64::MAX => 1.0,
+                    n_max => 1.0 - (1.0 - c).powf((n_max - n_min + 1) as f64),
+                };
+                let x = x - n_min + 1;
+                pmf(Geometric::new(*c), x, log).map(|p| match log {
+                    true => p - mass.ln(),
+                    false => p / mass,
+                })
             }
-            Dist::PBinomial(_, n_max, p) => {
+            Dist::PBinomial(n_min, n_max, p) => {
+                if x < *n_min {
+                    return scaled((1.0, 0.0), log);
+                }
                 if x > *n_max {
-                    return (0.0, 0.0);
+                    return scaled((0.0, 0.0), log);
+                }
+                pmf(Binomial::new(*p, n_max - n_min), x - n_min, log)
+            }
+            Dist::PBernoulli(n_min, n_max, p) => {
+                if x < *n_min || x > *n_max {
+                    return scaled((1.0, 0.0), log);
+                }
+                match n_min == n_max {
+                    // Truncated to a single value, which has all the mass
+                    true => Some(one(log)),
+                    false => pmf(Bernoulli::new(*p), x - n_min, log),
                 }
-                match log {
-                    true => Binomial::new(*p, *n_max).unwrap().ln_pmf(x),
-                    false => Binomial::new(*p, *n_max).unwrap().pmf(x),
+            }
+            Dist::PPoisson(n_min, n_max, lambda) => {
+                if x < *n_min {
+                    return scaled((1.0, 0.0), log);
+                }
+                if x > *n_max {
+                    return scaled((0.0, 0.0), log);
                 }
+                pmf_offset(Poisson::new(*lambda), x, *n_min, *n_max, log)
             }
-            Dist::PBernoulli(_, n_max, p) => {
+            Dist::PNegBinomial(n_min, n_max, r, p) => {
+                if x < *n_min {
+                    return scaled((1.0, 0.0), log);
+                }
                 if x > *n_max {
-                    return (1.0, 0.0);
+                    return scaled((0.0, 0.0), log);
+                }
+                pmf_offset(NegativeBinomial::new(*r, *p), x, *n_min, *n_max, log)
+            }
+            Dist::PNormal(n_min, n_max, mu, sigma) => {
+                if x < *n_min || x > *n_max {
+                    return scaled((1.0, 0.0), log);
+                }
+                pmf_discretized(Normal::new(*mu, *sigma), x, *n_min, *n_max, *mu, log)
+            }
+            Dist::PLaplace(n_min, n_max, mu, b) => {
+                if x < *n_min || x > *n_max {
+                    return scaled((1.0, 0.0), log);
                 }
-                match log {
-                    true => Bernoulli::new(*p).unwrap().ln_pmf(x),
-                    false => Bernoulli::new(*p).unwrap().pmf(x),
+                pmf_discretized(Laplace::new(*mu, *b), x, *n_min, *n_max, *mu, log)
+            }
+            Dist::PZeta(n_min, n_max, s) => {
+                if x < *n_min {
+                    return scaled((1.0, 0.0), log);
+                }
+                if x > *n_max {
+                    return scaled((0.0, 0.0), log);
                 }
+                let ln_p = -s * (x as f64).ln() - zeta_normalizer(*s, *n_min, *n_max).ln();
+                Some(match log {
+                    true => ln_p,
+                    false => ln_p.exp(),
+                })
             }
-            Dist::PZipf(_, n_max, s) => {
-                let p = zipf(x, *s, *n_max);
-                return (1. - p, p);
+            Dist::PZipf(n_min, n_max, s) => {
+                let p = zipf(x, *s, *n_min, *n_max);
+                return scaled((1. - p, p), log);
             }
             Dist::Categorical(prob_mass) => {
-                let p = match log {
-                    true => Categorical::new(prob_mass).unwrap().ln_pmf(x),
-                    false => Categorical::new(prob_mass).unwrap().pmf(x),
-                };
-                return (1. - p, p);
+                let p = pmf(Categorical::new(prob_mass), x, false).unwrap_or(0.0);
+                return scaled((1. - p, p), log);
             }
             _ => unreachable!(),
         };
+        let p = match p {
+            Some(p) => p,
+            // parameters out of range have no probability mass
+            None => return scaled((0.0, 0.0), log),
+        };
 
         // Calculate complement and return as out arrow probabilities (p0, p1)
         match log {
-            true => ((1. - p.exp()).ln(), p),
+            true => ((-p.exp()).ln_1p(), p),
             false => (1. - p, p),
         }
     }
 
+    /// Evaluate (p0, p1) for the character c at index x of a class, None if c is not a member
+    fn evaluate_indexed(&self, c: char, x: Option<u64>, is_inverse: bool, log: bool) -> (f64, f64) {
+        if let Dist::Mixture(components) = self {
+            let p: f64 = components
+                .iter()
+                .map(|(w, d)| w * d.evaluate_indexed(c, x, is_inverse, false).1)
+                .sum();
+            return scaled((1. - p, p), log);
+        }
+
+        if let Dist::Background(background) = self {
+            // only characters outside of the class are drawn from the background
+            let p = match (x, is_inverse) {
+                (None, true) => background.p(c),
+                _ => 0.0,
+            };
+            return scaled((1. - p, p), log);
+        }
+
+        if let Some(x) = x {
+            match self {
+                // zipf distribution has support for x > 0
+                Dist::PZipf(_, _, _) => self.evaluate(x + 1, log),
+                // categorical has support for x > 0 due to p_rest
+                Dist::Categorical(_) => self.evaluate(x + 1, log),
+                Dist::Constant(_, _, _) => self.evaluate(0, log),
+                _ => self.evaluate(x, log),
+            }
+        } else {
+            let (p0, p1) = match self {
+                Dist::Categorical(prob_mass) => {
+                    let p = prob_mass.first().unwrap();
+                    (1. - p, *p)
+                }
+                Dist::Constant(_, _, p) => match is_inverse {
+                    true => (*p, 1.),
+                    false => (*p, 1. - p),
+                },
+                _ => (0., 0.),
+            };
+            scaled((p0, p1), log)
+        }
+    }
+
     pub fn count(self) -> DistLink {
         DistLink::Counted(self)
     }
@@ -280,20 +762,196 @@ impl Dist {
     }
 }
 
+/// Point mass at `x`, or None if the distribution parameters were invalid
+fn pmf<D: Discrete<u64, f64>>(dist: statrs::Result<D>, x: u64, log: bool) -> Option<f64> {
+    let dist = dist.ok()?;
+    match log {
+        true => Some(dist.ln_pmf(x)),
+        false => Some(dist.pmf(x)),
+    }
+}
+
+/// Point mass at `x` of a distribution offset by n_min, renormalized when truncated at n_max
+fn pmf_offset<D: Discrete<u64, f64> + DiscreteCDF<u64, f64>>(
+    dist: statrs::Result<D>,
+    x: u64,
+    n_min: u64,
+    n_max: u64,
+    log: bool,
+) -> Option<f64> {
+    let dist = dist.ok()?;
+    let mass = match n_max {
+        u64::MAX => 1.0,
+        n_max => dist.cdf(n_max - n_min),
+    };
+    match log {
+        true => Some(dist.ln_pmf(x - n_min) - mass.ln()),
+        false => Some(dist.pmf(x - n_min) / mass),
+    }
+}
+
+/// Mass of a continuous distribution around `x`, i.e. on the values that round to `x`,
+/// renormalized to the values that round to n_min..=n_max
+fn pmf_discretized<D: ContinuousCDF<f64, f64>>(
+    dist: statrs::Result<D>,
+    x: u64,
+    n_min: u64,
+    n_max: u64,
+    mu: f64,
+    log: bool,
+) -> Option<f64> {
+    let dist = dist.ok()?;
+    // Subtract from the nearer tail, so the mass does not cancel out far from mu
+    let mass = |low: f64, high: f64| match low >= mu {
+        true => dist.sf(low) - dist.sf(high),
+        false => dist.cdf(high) - dist.cdf(low),
+    };
+    let x = x as f64;
+    let p = mass(x - 0.5, x + 0.5) / mass(n_min as f64 - 0.5, n_max as f64 + 0.5);
+    Some(scale(p, log))
+}
+
+/// Convert out arrow probabilities (p0, p1) from linear to linear or log space
+fn scaled((p0, p1): (f64, f64), log: bool) -> (f64, f64) {
+    (scale(p0, log), scale(p1, log))
+}
+
+/// Parse a numeric distribution parameter
+fn parse_param(span: Span, value: &str) -> Result<f64, CompileError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| CompileError::InvalidParameter(span, format!("{} is not a number", value)))
+}
+
+/// Parse the repetition count a Hist weight is keyed by
+fn parse_count(span: Span, value: &str) -> Result<u64, CompileError> {
+    value.trim().parse().map_err(|_| {
+        CompileError::InvalidParameter(span, format!("{} is not a repetition count", value))
+    })
+}
+
+/// Weighted counts of a Hist file, with a count and its weight on each line
+fn read_counts(span: Span, path: &str) -> Result<Vec<(Span, u64, f64)>, CompileError> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        CompileError::InvalidParameter(span, format!("cannot read {}: {}", path, e))
+    })?;
+    let mut counts = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[..] {
+            [] => {}
+            [n, w] => counts.push((span, parse_count(span, n)?, parse_param(span, w)?)),
+            _ => {
+                return Err(CompileError::InvalidParameter(
+                    span,
+                    format!("{} line {}: expected a count and a weight", path, i + 1),
+                ))
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Fail with an invalid parameter error unless condition holds
+fn ensure<S: Into<String>>(condition: bool, span: Span, message: S) -> Result<(), CompileError> {
+    match condition {
+        true => Ok(()),
+        false => Err(CompileError::InvalidParameter(span, message.into())),
+    }
+}
+
+/// Fail unless a quantifier bound is finite
+fn ensure_bounded(n_max: u64, span: Span, name: &str) -> Result<(), CompileError> {
+    match n_max {
+        u64::MAX => Err(CompileError::InvalidDistribution(
+            span,
+            format!("{} needs an upper bound on repetitions", name),
+        )),
+        _ => Ok(()),
+    }
+}
+
 /// Calculates the probability mass function for the zipf distribution at `x`
 // The Zipf distribution reduces to the Zeta distribution as n -> inf
-fn zipf(x: u64, a: f64, n: u64) -> f64 {
+fn zipf(x: u64, a: f64, n_min: u64, n_max: u64) -> f64 {
     // Support zero for consistency
-    if x == 0 {
+    if x == 0 || x < n_min {
         return 0.0;
     }
 
-    let normalizer = generalized_harmonic_number(n, a);
+    // Normalize over the ranks n_min..=n_max, or 1..=n_max if n_min is zero
+    let normalizer = generalized_harmonic_number(n_max, a)
+        - generalized_harmonic_number(n_min.saturating_sub(1), a);
     (1.0 / (x as f64).powf(a)) / normalizer
 }
 
+/// Number of alternatives in a chain of alternations, which nest to the right
+fn count_branches(kind: &Kind) -> usize {
+    match kind {
+        // an alternation weighed on its own is a single branch of this one
+        Kind::Alternation(_, right, _) => match right.kind {
+            Kind::Alternation(_, _, None) => 1 + count_branches(&right.kind),
+            _ => 2,
+        },
+        _ => 1,
+    }
+}
+
+thread_local! {
+    /// Generalized harmonic numbers by n and the bits of m, as bounded Zipf
+    /// distributions normalize by them on every evaluation
+    static HARMONIC_NUMBERS: RefCell<HashMap<(u64, u64), f64>> = RefCell::new(HashMap::new());
+}
+
+/// Sum of 1 / k^m for k in 1..=n
 fn generalized_harmonic_number(n: u64, m: f64) -> f64 {
-    (1..(n + 1)).map(|n_i| 1.0 / (n_i as f64).powf(m)).sum()
+    if let Some(h) = HARMONIC_NUMBERS.with(|cache| cache.borrow().get(&(n, m.to_bits())).copied()) {
+        return h;
+    }
+    let h = match m > 1.0 && n > 1000 {
+        // The sum converges, so it is the rest after the tail
+        true => hurwitz_zeta(m, 1.0) - hurwitz_zeta(m, (n + 1) as f64),
+        false => (1..(n + 1)).map(|n_i| 1.0 / (n_i as f64).powf(m)).sum(),
+    };
+    HARMONIC_NUMBERS.with(|cache| cache.borrow_mut().insert((n, m.to_bits()), h));
+    h
+}
+
+/// Mass of the ranks n_min..=n_max under 1 / k^s, which needs n_min >= 1, and s > 1 when unbounded
+pub(crate) fn zeta_normalizer(s: f64, n_min: u64, n_max: u64) -> f64 {
+    let head = hurwitz_zeta(s, n_min as f64);
+    match n_max {
+        u64::MAX => head,
+        n_max => head - hurwitz_zeta(s, (n_max + 1) as f64),
+    }
+}
+
+/// Hurwitz zeta function, the sum of 1 / (k + q)^s over k >= 0, for s > 1 and q > 0
+///
+/// The first terms are summed directly, the tail by Euler-Maclaurin summation.
+pub(crate) fn hurwitz_zeta(s: f64, q: f64) -> f64 {
+    const N_DIRECT: u64 = 9;
+    // B_2j / (2j)! for j in 1..=6
+    const BERNOULLI: [f64; 6] = [
+        1.0 / 12.0,
+        -1.0 / 720.0,
+        1.0 / 30240.0,
+        -1.0 / 1209600.0,
+        1.0 / 47900160.0,
+        -691.0 / 1307674368000.0,
+    ];
+    let head: f64 = (0..N_DIRECT).map(|k| (k as f64 + q).powf(-s)).sum();
+    let a = N_DIRECT as f64 + q;
+    let mut tail = a.powf(1.0 - s) / (s - 1.0) + 0.5 * a.powf(-s);
+    // s (s + 1) ... (s + 2j - 2) / a^(s + 2j - 1)
+    let mut factor = s * a.powf(-s - 1.0);
+    for (j, b) in BERNOULLI.iter().enumerate() {
+        tail += b * factor;
+        let j = j as f64;
+        factor *= (s + 2.0 * j + 1.0) * (s + 2.0 * j + 2.0) / (a * a);
+    }
+    head + tail
 }
 
 /// Link for mapping state parameters to distribution parameters
@@ -324,39 +982,47 @@ impl DistLink {
                     Kind::Literal(c) => c,
                     _ => {
                         // skip non literals for now
-                        return (0., 0.);
+                        return scaled((0., 0.), log);
                     }
                 };
 
                 // TODO add to PR comments that this changed, so no longer pass Option<x> instead handle None in pmf_link
-
-                if let Some(x) = x {
-                    match d {
-                        // zipf distribution has support for x > 0
-                        Dist::PZipf(_, _, _) => d.evaluate(x + 1, log),
-                        // categorical has support for x > 0 due to p_rest
-                        Dist::Categorical(_) => d.evaluate(x + 1, log),
-                        Dist::Constant(_, _, _) => d.evaluate(0, log),
-                        _ => d.evaluate(x, log),
-                    }
-                } else {
-                    match d {
-                        Dist::Categorical(prob_mass) => {
-                            let p = prob_mass.get(0).unwrap();
-                            (1. - p, *p)
-                        }
-                        Dist::Constant(_, _, p) => match is_inverse {
-                            true => (*p, 1.),
-                            false => (*p, 1. - p),
-                        },
-                        _ => (0., 0.),
-                    }
-                }
+                d.evaluate_indexed(*c, x, is_inverse, log)
             }
         };
 
         (p0, p1)
     }
+
+    /// Probabilities of the (left, right) outs of a split state, in linear or log space
+    ///
+    /// The split chooses between the first of the branches the categorical weighs
+    /// and the rest of them, whose weights are applied further down the chain of splits.
+    pub fn pmf_split(&self, log: bool) -> (f64, f64) {
+        match self {
+            DistLink::Indexed(Dist::Categorical(prob_mass)) => match prob_mass.len() {
+                // last split of the chain, between the last two branches
+                3 => scaled((prob_mass[1], prob_mass[2]), log),
+                _ => scaled((prob_mass[1], 1.0), log),
+            },
+            _ => scaled((1.0, 1.0), log),
+        }
+    }
+
+    /// Distribution over the branches after the first, for the next split of the chain
+    ///
+    /// None once the next branch is the last one, which may itself be a group of alternatives.
+    pub fn rest_branches(&self) -> Option<DistLink> {
+        match self {
+            DistLink::Indexed(Dist::Categorical(prob_mass)) if prob_mass.len() <= 3 => None,
+            DistLink::Indexed(Dist::Categorical(prob_mass)) => {
+                let mut prob_mass = prob_mass.clone();
+                prob_mass.remove(1);
+                Some(DistLink::Indexed(Dist::Categorical(prob_mass)))
+            }
+            link => Some(link.clone()),
+        }
+    }
 }
 
 impl fmt::Display for DistLink {
@@ -391,7 +1057,44 @@ mod test {
     fn test_distribution_constant_log() {
         assert_tuple_nearly_eq(Dist::Constant(0, 1, 1.0).evaluated(1, true), (0., 0.), 0.01);
         assert_tuple_nearly_eq(Dist::Constant(0, 1, 0.5).evaluated(1, true), (-0.69, -0.69), 0.01);
-        assert_tuple_nearly_eq(Dist::Constant(0, 1, 0.5).evaluated(2, true), (0., 0.), 0.01);
+        assert_eq!(Dist::Constant(0, 1, 0.5).evaluated(2, true), (f64::NEG_INFINITY, f64::NEG_INFINITY));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_distribution_log() {
+        // log space agrees with linear space, with -inf for no mass
+        let dists = [
+            Dist::ExactlyTimes(2),
+            Dist::PGeometric(1, 3, 0.5),
+            Dist::PBinomial(0, 2, 0.5),
+            Dist::PBernoulli(0, 1, 0.3),
+            Dist::PZipf(0, 2, 1.0),
+            Dist::PZeta(1, u64::MAX, 2.5),
+            Dist::PPoisson(1, 3, 1.5),
+            Dist::PNegBinomial(0, u64::MAX, 2.5, 0.4),
+            Dist::PNormal(0, u64::MAX, 2.0, 1.5),
+            Dist::PLaplace(1, 3, 2.0, 1.0),
+            Dist::Mixture(vec![(0.4, Dist::PZipf(0, 2, 1.0)), (0.6, Dist::PPoisson(0, 9, 1.0))]),
+            Dist::Histogram([(1, 0.4), (3, 0.6)].into()),
+            Dist::Categorical(vec![0.5, 0.3, 0.2]),
+        ];
+        for dist in dists.iter() {
+            for x in 0..5 {
+                let (p0, p1) = dist.evaluated(x, false);
+                let (l0, l1) = dist.evaluated(x, true);
+                assert_relative_eq!(l0.exp(), p0, epsilon = 1e-12);
+                assert_relative_eq!(l1.exp(), p1, epsilon = 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_distribution_log_tail() {
+        // far in the tail, the linear pmf underflows but the log pmf does not
+        let dist = Dist::PGeometric(1, u64::MAX, 0.5);
+        assert_eq!(dist.evaluated(2000, false).1, 0.0);
+        assert_relative_eq!(dist.evaluated(2000, true).1, 2000. * 0.5_f64.ln());
     }
 
     #[test]
@@ -418,6 +1121,25 @@ mod test {
         assert_eq!(Dist::PGeometric(2, u64::MAX, 0.5).evaluated(2, false), (0.5, 0.5));
     }
 
+    #[test]
+    #[rustfmt::skip]
+    fn test_distribution_geometric_truncated() {
+        // truncated to 2..=3, so mass is 1 - 0.5^2
+        assert_eq!(Dist::PGeometric(2, 3, 0.5).evaluated(1, false), (1.0, 0.0));
+        assert_eq!(Dist::PGeometric(2, 3, 0.5).evaluated(2, false), (1. - 0.5 / 0.75, 0.5 / 0.75));
+        assert_eq!(Dist::PGeometric(2, 3, 0.5).evaluated(3, false), (1. - 0.25 / 0.75, 0.25 / 0.75));
+        assert_eq!(Dist::PGeometric(2, 3, 0.5).evaluated(4, false), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_distribution_binomial_offset() {
+        use Dist::PBinomial;
+        assert_eq!(PBinomial(2, 4, 0.5).evaluated(1, false), (1.0, 0.0));
+        assert_eq!(PBinomial(2, 4, 0.5).evaluated(2, false), (0.75, 0.25));
+        assert_eq!(PBinomial(2, 4, 0.5).evaluated(3, false), (0.5, 0.5));
+        assert_eq!(PBinomial(2, 4, 0.5).evaluated(5, false), (0.0, 0.0));
+    }
+
     #[test]
     fn test_distribution_binomial_degenerate() {
         // p = 0, the distribution is concentrated at 0
@@ -448,6 +1170,208 @@ mod test {
         assert_eq!(PBinomial(0, 2, 0.5).evaluated(3, false), (0.0, 0.0));
     }
 
+    #[test]
+    #[rustfmt::skip]
+    fn test_distribution_poisson() {
+        // e^-2 2^k / k!, offset by 1
+        let dist = Dist::PPoisson(1, u64::MAX, 2.0);
+        let e = (-2.0_f64).exp();
+        assert_eq!(dist.evaluated(0, false), (1.0, 0.0));
+        assert_relative_eq!(dist.evaluated(1, false).1, e);
+        assert_relative_eq!(dist.evaluated(3, false).1, e * 4.0 / 2.0);
+
+        // truncated to 1..=2, so mass is e^-2 (1 + 2)
+        let dist = Dist::PPoisson(1, 2, 2.0);
+        assert_relative_eq!(dist.evaluated(1, false).1, 1.0 / 3.0, epsilon = 1e-12);
+        assert_relative_eq!(dist.evaluated(2, false).1, 2.0 / 3.0, epsilon = 1e-12);
+        assert_eq!(dist.evaluated(3, false), (0.0, 0.0));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_distribution_negative_binomial() {
+        // with r = 1 it is a geometric on the failures
+        let dist = Dist::PNegBinomial(2, u64::MAX, 1.0, 0.5);
+        assert_eq!(dist.evaluated(1, false), (1.0, 0.0));
+        assert_relative_eq!(dist.evaluated(2, false).1, 0.5);
+        assert_relative_eq!(dist.evaluated(3, false).1, 0.25);
+
+        // (k + 1) p^2 (1 - p)^k for r = 2
+        let dist = Dist::PNegBinomial(0, u64::MAX, 2.0, 0.5);
+        assert_relative_eq!(dist.evaluated(2, false).1, 3.0 * 0.25 * 0.25, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_distribution_normal() {
+        // symmetric around 8, and renormalized for the mass below -0.5
+        let dist = Dist::PNormal(0, u64::MAX, 8.0, 1.5);
+        let (_, p8) = dist.evaluated(8, false);
+        assert_relative_eq!(dist.evaluated(7, false).1, dist.evaluated(9, false).1);
+        assert!(p8 > dist.evaluated(7, false).1);
+        assert_relative_eq!(
+            (0..100).map(|x| dist.evaluated(x, false).1).sum::<f64>(),
+            1.0,
+            epsilon = 1e-9
+        );
+
+        // centered on zero, so half of the mass is cut off
+        let dist = Dist::PNormal(0, u64::MAX, 0.0, 1.0);
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        assert_relative_eq!(
+            dist.evaluated(0, false).1,
+            (normal.cdf(0.5) - normal.cdf(-0.5)) / normal.sf(-0.5)
+        );
+
+        // far in the tail, the mass does not cancel out
+        assert!(dist.evaluated(9, true).1 > f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_distribution_laplace() {
+        // mass e^-|x - mu| / b within 0.5 of mu, and the rest split over the tails,
+        // up to the little mass below -0.5
+        let dist = Dist::PLaplace(0, u64::MAX, 8.0, 1.0);
+        assert_relative_eq!(
+            dist.evaluated(8, false).1,
+            1.0 - (-0.5_f64).exp(),
+            epsilon = 1e-4
+        );
+        assert_relative_eq!(
+            dist.evaluated(10, false).1,
+            ((-1.5_f64).exp() - (-2.5_f64).exp()) / 2.0,
+            epsilon = 1e-4
+        );
+
+        // truncated to 7..=9
+        let dist = Dist::PLaplace(7, 9, 8.0, 1.0);
+        assert_eq!(dist.evaluated(6, false), (1.0, 0.0));
+        assert_relative_eq!(
+            (7..=9).map(|x| dist.evaluated(x, false).1).sum::<f64>(),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_distribution_mixture() {
+        let dist = Dist::Mixture(vec![
+            (0.25, Dist::PGeometric(1, u64::MAX, 0.5)),
+            (0.75, Dist::PNormal(0, u64::MAX, 10.0, 1.0)),
+        ]);
+        for x in [0, 1, 2, 10] {
+            let p = 0.25 * Dist::PGeometric(1, u64::MAX, 0.5).evaluated(x, false).1
+                + 0.75 * Dist::PNormal(0, u64::MAX, 10.0, 1.0).evaluated(x, false).1;
+            assert_relative_eq!(dist.evaluated(x, false).1, p);
+        }
+
+        // members of a class are indexed by each component, the remainder too
+        let dist = Dist::Mixture(vec![
+            (0.5, Dist::Categorical(vec![0.2, 0.8, 0.0])),
+            (0.5, Dist::PGeometric(0, u64::MAX, 0.5)),
+        ]);
+        assert_relative_eq!(dist.evaluate_indexed('a', Some(0), false, false).1, 0.65);
+        assert_relative_eq!(dist.evaluate_indexed('b', Some(1), false, false).1, 0.125);
+        assert_relative_eq!(dist.evaluate_indexed('z', None, false, false).1, 0.1);
+    }
+
+    #[test]
+    fn test_distribution_histogram() {
+        let dist = Dist::Histogram([(3, 0.25), (5, 0.75)].into());
+        assert_eq!(dist.evaluated(2, false), (1.0, 0.0));
+        assert_eq!(dist.evaluated(3, false), (0.75, 0.25));
+        assert_eq!(dist.evaluated(4, false), (1.0, 0.0));
+        assert_eq!(dist.evaluated(5, false), (0.25, 0.75));
+    }
+
+    #[test]
+    fn test_distribution_saturation() {
+        // memoryless from the smallest count
+        let dist = Dist::PGeometric(2, u64::MAX, 0.25);
+        assert_eq!(dist.saturation(2), None);
+        assert_eq!(dist.saturation(3), Some((2, 0.75)));
+        assert_eq!(
+            Dist::Constant(1, u64::MAX, 1.0).saturation(9),
+            Some((1, 1.0))
+        );
+        assert_eq!(Dist::PGeometric(2, 5, 0.25).saturation(5), None);
+
+        // past the last count of a histogram there is no mass
+        let dist = Dist::Histogram([(3, 0.25), (5, 0.75)].into());
+        assert_eq!(dist.saturation(4), None);
+        assert_eq!(dist.saturation(6), Some((5, 0.0)));
+
+        // the Poisson tail does not decay by a constant ratio, nor the power law of zeta
+        assert_eq!(Dist::PPoisson(0, u64::MAX, 2.0).saturation(100), None);
+        assert_eq!(Dist::PZeta(1, u64::MAX, 2.0).saturation(1_000_000), None);
+    }
+
+    #[test]
+    fn test_distribution_zeta() {
+        // 1 / k^2 normalized by pi^2 / 6
+        let zeta_2 = std::f64::consts::PI.powi(2) / 6.0;
+        let dist = Dist::PZeta(1, u64::MAX, 2.0);
+        assert_eq!(dist.evaluated(0, false), (1.0, 0.0));
+        assert_relative_eq!(dist.evaluated(1, false).1, 1.0 / zeta_2, epsilon = 1e-12);
+        assert_relative_eq!(
+            dist.evaluated(3, false).1,
+            1.0 / 9.0 / zeta_2,
+            epsilon = 1e-12
+        );
+        assert_relative_eq!(
+            dist.evaluated(1_000_000, true).1,
+            -2.0 * 1e6_f64.ln() - zeta_2.ln(),
+            epsilon = 1e-9
+        );
+
+        // from 2 on, without the mass at 1
+        let dist = Dist::PZeta(2, u64::MAX, 2.0);
+        assert_relative_eq!(
+            dist.evaluated(2, false).1,
+            0.25 / (zeta_2 - 1.0),
+            epsilon = 1e-12
+        );
+
+        // bounded, it agrees with Zipf
+        let zeta = Dist::PZeta(2, 40, 1.5);
+        let zipf = Dist::PZipf(2, 40, 1.5);
+        for x in [2, 7, 40] {
+            assert_relative_eq!(
+                zeta.evaluated(x, false).1,
+                zipf.evaluated(x, false).1,
+                epsilon = 1e-12
+            );
+        }
+    }
+
+    #[test]
+    fn test_hurwitz_zeta() {
+        use std::f64::consts::PI;
+        assert_relative_eq!(hurwitz_zeta(2.0, 1.0), PI.powi(2) / 6.0, epsilon = 1e-14);
+        assert_relative_eq!(hurwitz_zeta(4.0, 1.0), PI.powi(4) / 90.0, epsilon = 1e-14);
+        assert_relative_eq!(hurwitz_zeta(1.1, 1.0), 10.584448464950809, epsilon = 1e-12);
+        assert_relative_eq!(
+            hurwitz_zeta(2.0, 3.0),
+            PI.powi(2) / 6.0 - 1.25,
+            epsilon = 1e-14
+        );
+    }
+
+    #[test]
+    fn test_generalized_harmonic_number() {
+        assert_eq!(generalized_harmonic_number(2, 1.0), 1.5);
+        // past 1000 terms the sum is the zeta function less its tail
+        let direct: f64 = (1..=5000).map(|k| (k as f64).powf(-1.5)).sum();
+        assert_relative_eq!(
+            generalized_harmonic_number(5000, 1.5),
+            direct,
+            epsilon = 1e-12
+        );
+        assert_relative_eq!(
+            generalized_harmonic_number(5000, 1.5),
+            direct,
+            epsilon = 1e-12
+        );
+    }
+
     #[test]
     fn test_distribution_bernoulli() {
         assert_eq!(Dist::PBernoulli(0, 1, 0.5).evaluated(0, false), (0.5, 0.5));
@@ -477,5 +1401,38 @@ mod test {
         let dist = Dist::PZipf(0, 2, 1.0);
         assert_eq!(dist.evaluated(1, false), (1. - (1. / 1.) / 1.5, (1. / 1.) / 1.5));
         assert_eq!(dist.evaluated(2, false), (1. - (1. / 2.) / 1.5, (1. / 2.) / 1.5));
+
+        // n_min = 2, n_max = 3, the normalizing constant is 1/2 + 1/3
+        let dist = Dist::PZipf(2, 3, 1.0);
+        assert_eq!(dist.evaluated(1, false), (1.0, 0.0));
+        assert_tuple_nearly_eq(dist.evaluated(2, false), (0.4, 0.6), 0.01);
+    }
+
+    #[test]
+    fn test_distribution_invalid_params() {
+        // invalid parameters have no mass, rather than panicking
+        assert_eq!(
+            Dist::PGeometric(0, u64::MAX, 1.5).evaluated(1, false),
+            (0.0, 0.0)
+        );
+        assert_eq!(Dist::PBinomial(0, 2, -0.5).evaluated(1, false), (0.0, 0.0));
+        assert_eq!(Dist::PBernoulli(0, 1, 2.0).evaluated(1, false), (0.0, 0.0));
+        assert_eq!(
+            Dist::Categorical(vec![0.0, 0.0]).evaluated(1, false),
+            (1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_dist_link_pmf_split() {
+        // a chain of three branches, with 0.1 of the mass on none of them
+        let link = Dist::Categorical(vec![0.1, 0.2, 0.3, 0.4]).index();
+        assert_eq!(link.pmf_split(false), (0.2, 1.0));
+        let rest = link.rest_branches().unwrap();
+        assert_eq!(rest, Dist::Categorical(vec![0.1, 0.3, 0.4]).index());
+        assert_eq!(rest.pmf_split(false), (0.3, 0.4));
+        assert_eq!(rest.pmf_split(true), (0.3_f64.ln(), 0.4_f64.ln()));
+        // the last branch is not split any further
+        assert_eq!(rest.rest_branches(), None);
     }
 }
@@ -0,0 +1,146 @@
+use crate::parser::Rule;
+use pest::error::InputLocation;
+use std::fmt;
+
+/// Byte offsets of a fragment of the pattern source
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(span: pest::Span<'_>) -> Self {
+        Span::new(span.start(), span.end())
+    }
+}
+
+/// Error raised while compiling a pattern
+///
+/// Every variant carries the span of the offending part of the pattern,
+/// so that diagnostics can point at it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum CompileError {
+    /// Pattern does not follow the grammar
+    Syntax(Span, String),
+    /// Character class is not known
+    UnknownClass(Span, String),
+    /// Character class is malformed
+    InvalidClass(Span, String),
+    /// Escape sequence does not name a character
+    InvalidEscape(Span, String),
+    /// Distribution name is not known
+    UnknownDistribution(Span, String),
+    /// Distribution can not be used in this position or with these parameters
+    InvalidDistribution(Span, String),
+    /// Distribution parameter is malformed or out of its valid range
+    InvalidParameter(Span, String),
+    /// Quantifier is malformed
+    InvalidQuantifier(Span, String),
+}
+
+impl CompileError {
+    pub fn span(&self) -> Span {
+        match self {
+            CompileError::Syntax(span, _)
+            | CompileError::UnknownClass(span, _)
+            | CompileError::InvalidClass(span, _)
+            | CompileError::InvalidEscape(span, _)
+            | CompileError::UnknownDistribution(span, _)
+            | CompileError::InvalidDistribution(span, _)
+            | CompileError::InvalidParameter(span, _)
+            | CompileError::InvalidQuantifier(span, _) => *span,
+        }
+    }
+
+    pub(crate) fn span_mut(&mut self) -> &mut Span {
+        match self {
+            CompileError::Syntax(span, _)
+            | CompileError::UnknownClass(span, _)
+            | CompileError::InvalidClass(span, _)
+            | CompileError::InvalidEscape(span, _)
+            | CompileError::UnknownDistribution(span, _)
+            | CompileError::InvalidDistribution(span, _)
+            | CompileError::InvalidParameter(span, _)
+            | CompileError::InvalidQuantifier(span, _) => span,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            CompileError::Syntax(_, msg) => format!("syntax error, {}", msg),
+            CompileError::UnknownClass(_, name) => format!("unknown character class {}", name),
+            CompileError::UnknownDistribution(_, name) => format!("unknown distribution {}", name),
+            CompileError::InvalidClass(_, msg)
+            | CompileError::InvalidEscape(_, msg)
+            | CompileError::InvalidDistribution(_, msg)
+            | CompileError::InvalidParameter(_, msg)
+            | CompileError::InvalidQuantifier(_, msg) => msg.to_string(),
+        }
+    }
+
+    /// Render the error with the offending part of source underlined
+    pub fn annotate(&self, source: &str) -> String {
+        let span = self.span();
+        let offset = source.get(..span.start).unwrap_or(source).chars().count();
+        let width = source
+            .get(span.start..span.end)
+            .unwrap_or("")
+            .chars()
+            .count();
+        format!(
+            "error: {}\n  {}\n  {}{}",
+            self.message(),
+            source,
+            " ".repeat(offset),
+            "^".repeat(usize::max(1, width))
+        )
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message(), self.span().start)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+impl From<pest::error::Error<Rule>> for CompileError {
+    fn from(error: pest::error::Error<Rule>) -> Self {
+        let span = match error.location {
+            InputLocation::Pos(pos) => Span::new(pos, pos),
+            InputLocation::Span((start, end)) => Span::new(start, end),
+        };
+        CompileError::Syntax(span, error.variant.message().to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_annotate() {
+        let error = CompileError::UnknownDistribution(Span::new(3, 7), "Foo".to_string());
+        assert_eq!(
+            error.annotate("a{2~Foo}"),
+            "error: unknown distribution Foo\n  a{2~Foo}\n     ^^^^"
+        );
+    }
+
+    #[test]
+    fn test_annotate_position() {
+        let error = CompileError::Syntax(Span::new(2, 2), "expected ...".to_string());
+        assert_eq!(
+            error.annotate("a{"),
+            "error: syntax error, expected ...\n  a{\n    ^"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{nfa::State, regex::find_at, semiring::Semiring};
+use std::fmt;
+use std::ops::Range;
+
+/// Span of text matched by a pattern, with byte offsets into the text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    /// Log-likelihood of the matched text, with paths merged by the semiring of the pattern
+    pub log_likelihood: f64,
+}
+
+impl Match {
+    /// Likelihood of the matched text, which may underflow to zero on long matches
+    pub fn likelihood(&self) -> f64 {
+        self.log_likelihood.exp()
+    }
+
+    /// Byte range of the match in the text
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}\t{:.5}", self.start, self.end, self.likelihood())
+    }
+}
+
+/// Iterator over the successive non-overlapping matches in a text
+///
+/// Each match is the most likely one after the end of the previous match,
+/// see `regex::find_at` for how ties are broken. An empty match right where
+/// the previous match ended is skipped.
+#[derive(Debug, Clone)]
+pub struct Matches<'p, 't> {
+    nfa: &'p [State],
+    semiring: Semiring,
+    text: &'t str,
+    from: Option<usize>,
+    last_end: Option<usize>,
+}
+
+impl<'p, 't> Matches<'p, 't> {
+    pub fn new(nfa: &'p [State], semiring: Semiring, text: &'t str) -> Matches<'p, 't> {
+        Matches {
+            nfa,
+            semiring,
+            text,
+            from: Some(0),
+            last_end: None,
+        }
+    }
+}
+
+impl Iterator for Matches<'_, '_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        loop {
+            let found = find_at(self.nfa, self.text, self.from?, self.semiring);
+            let m = match found {
+                Some(m) => m,
+                None => {
+                    self.from = None;
+                    return None;
+                }
+            };
+            if !m.is_empty() {
+                self.from = Some(m.end);
+                self.last_end = Some(m.end);
+                return Some(m);
+            }
+            // Step past an empty match, so that it is not found again
+            self.from = self.text[m.end..]
+                .chars()
+                .next()
+                .map(|c| m.end + c.len_utf8());
+            // and skip it where the previous match ended, so that a* finds 0..0 and 1..3
+            // in "baa", where the regex crate also finds the empty match at 3..3
+            if self.last_end != Some(m.end) {
+                self.last_end = Some(m.end);
+                return Some(m);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compile;
+
+    fn spans(source: &str, text: &str) -> Vec<(usize, usize)> {
+        let nfa = compile(source).unwrap();
+        Matches::new(&nfa, Semiring::MaxProduct, text)
+            .map(|m| (m.start, m.end))
+            .collect()
+    }
+
+    #[test]
+    fn test_matches() {
+        assert_eq!(spans("abc", "xxabcyyabc"), vec![(2, 5), (7, 10)]);
+        assert_eq!(spans("abc", "xxabyy"), vec![]);
+        assert_eq!(spans("aa", "aaaaa"), vec![(0, 2), (2, 4)]);
+    }
+
+    #[test]
+    fn test_matches_anchored() {
+        assert_eq!(spans("^ab", "abab"), vec![(0, 2)]);
+        assert_eq!(spans("ab$", "abab"), vec![(2, 4)]);
+        assert_eq!(spans("^ab$", "abab"), vec![]);
+    }
+
+    #[test]
+    fn test_matches_empty() {
+        assert_eq!(spans("a*", "baa"), vec![(0, 0), (1, 3)]);
+        assert_eq!(spans("x?", "é"), vec![(0, 0), (2, 2)]);
+    }
+
+    #[test]
+    fn test_matches_zero_likelihood() {
+        assert_eq!(
+            spans("a{1~Geo(0.5)}", "baaab aab"),
+            vec![(1, 2), (2, 3), (3, 4), (6, 7), (7, 8)]
+        );
+    }
+
+    #[test]
+    fn test_match_display() {
+        let m = Match {
+            start: 2,
+            end: 5,
+            log_likelihood: 0.5_f64.ln(),
+        };
+        assert_eq!(m.to_string(), "2..5\t0.50000");
+        assert_eq!(m.range(), 2..5);
+        assert!(!m.is_empty());
+    }
+}
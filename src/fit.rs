@@ -0,0 +1,603 @@
+use crate::{
+    ast::{AstNode, Kind},
+    charclass::member_index,
+    distribution::{zeta_normalizer, Dist, DistLink},
+    error::{CompileError, Span},
+    nfa::State,
+    parser,
+    pattern::Pattern,
+    regex::best_path,
+};
+use itertools::Itertools;
+use std::collections::HashMap;
+
+/// Pattern with distribution parameters fitted to a corpus
+#[derive(Debug, Clone)]
+pub struct Fit {
+    pub pattern: Pattern,
+    /// Log-likelihood of the corpus under the best paths of the original pattern
+    pub log_likelihood_before: f64,
+    /// Log-likelihood of the corpus under the best paths of the fitted pattern
+    pub log_likelihood_after: f64,
+    /// Lines of the corpus the original pattern does not match, which are left out
+    pub n_unmatched: usize,
+    pub iterations: usize,
+}
+
+/// Fit the distribution parameters of pattern to the lines of corpus
+///
+/// Runs hard (Viterbi) expectation maximization: the best path of every line
+/// gives the repetition counts of each quantifier and the characters of each
+/// class and the branches of each weighted alternation, from which the maximum
+/// likelihood parameters are estimated, until the log-likelihood of the corpus
+/// stops improving. Geometric, binomial, Bernoulli, Zipf and categorical
+/// parameters are fitted, bounds are kept.
+pub fn fit<S: AsRef<str>>(
+    pattern: &Pattern,
+    corpus: &[S],
+    max_iterations: usize,
+) -> Result<Fit, CompileError> {
+    let source = pattern.as_str();
+    let states = fitted_states(pattern.ast());
+    let spans: Vec<_> = parser::dist_spans(source)?
+        .into_iter()
+        .filter(|span| is_fitted_source(&source[span.start..span.end]))
+        .collect();
+    if states.len() != spans.len() {
+        return Err(CompileError::InvalidDistribution(
+            Span::new(0, source.len()),
+            format!(
+                "{} distributions to fit in source, but {} in the compiled pattern",
+                spans.len(),
+                states.len()
+            ),
+        ));
+    }
+
+    let mut nfa = pattern.nfa().to_vec();
+    let (log_likelihood_before, n_unmatched) = corpus_log_likelihood(&nfa, corpus);
+    let mut log_likelihood = log_likelihood_before;
+    let mut iterations = 0;
+
+    while iterations < max_iterations {
+        let observations = observe(&nfa, corpus, &states);
+        let mut fitted = nfa.clone();
+        for idx in states.iter() {
+            if let (Some(xs), Some(dist)) = (observations.get(idx), &nfa[*idx].dist) {
+                fitted[*idx].dist = Some(match dist {
+                    DistLink::Counted(d) => DistLink::Counted(estimate(d, xs)),
+                    DistLink::Indexed(d) => DistLink::Indexed(estimate(d, xs)),
+                });
+            }
+            // The rest of the chain of splits weighs the remaining branches
+            let mut rest = fitted[*idx].dist.clone();
+            for split in branch_splits(&nfa, *idx).into_iter().skip(1) {
+                rest = rest.and_then(|dist| dist.rest_branches());
+                fitted[split].dist = rest.clone();
+            }
+        }
+        iterations += 1;
+
+        let (fitted_log_likelihood, _) = corpus_log_likelihood(&fitted, corpus);
+        if fitted_log_likelihood <= log_likelihood {
+            break;
+        }
+        let converged = fitted_log_likelihood - log_likelihood < 1e-9;
+        nfa = fitted;
+        log_likelihood = fitted_log_likelihood;
+        if converged {
+            break;
+        }
+    }
+
+    // Print the fitted distributions back into source
+    let mut fitted_source = String::new();
+    let mut end = 0;
+    for (span, idx) in spans.iter().zip(states.iter()) {
+        fitted_source.push_str(&source[end..span.start]);
+        fitted_source.push_str(&render(&nfa[*idx]));
+        end = span.end;
+    }
+    fitted_source.push_str(&source[end..]);
+    let fitted_pattern = Pattern::new(&fitted_source)?.with_semiring(pattern.semiring());
+
+    Ok(Fit {
+        log_likelihood_after: corpus_log_likelihood(fitted_pattern.nfa(), corpus).0,
+        pattern: fitted_pattern,
+        log_likelihood_before,
+        n_unmatched,
+        iterations,
+    })
+}
+
+/// NFA states with a distribution to fit, in the order their distributions appear in source
+fn fitted_states(asts: &[AstNode]) -> Vec<usize> {
+    fn visit(node: &AstNode, index: usize, states: &mut Vec<usize>) {
+        match &node.kind {
+            Kind::Concatenation(left, right) => {
+                visit(left, index, states);
+                visit(right, index + left.length, states);
+            }
+            Kind::Alternation(left, right, dist) => {
+                // the split comes first, but the distribution closes the group
+                visit(left, index + 1, states);
+                visit(right, index + 1 + left.length, states);
+                if is_fitted(dist) {
+                    states.push(index);
+                }
+            }
+            Kind::Quantified(_, quantified, dist) => {
+                // the quantifier state comes first, but its distribution last
+                visit(quantified, index + 1, states);
+                if is_fitted(dist) {
+                    states.push(index);
+                }
+            }
+            Kind::Group(_, _, inner) => visit(inner, index + 1, states),
+            Kind::Classified(_, dist) if is_fitted(dist) => states.push(index),
+            _ => {}
+        }
+    }
+
+    // states of the expressions start after the start state
+    let mut states = Vec::new();
+    let mut index = 1;
+    for ast in asts {
+        visit(ast, index, &mut states);
+        index += ast.length;
+    }
+    states
+}
+
+/// Splits of the chain that weighs the branches of the alternation at idx, from idx on
+///
+/// Splits of other states, and weighted alternations nested as a branch, are left out.
+fn branch_splits(nfa: &[State], idx: usize) -> Vec<usize> {
+    let n_splits = match (&nfa[idx].kind, &nfa[idx].dist) {
+        (Kind::Split, Some(DistLink::Indexed(Dist::Categorical(prob_mass)))) => prob_mass.len() - 2,
+        _ => 0,
+    };
+    std::iter::successors(Some(idx), |split| nfa[*split].outs.1)
+        .take(n_splits)
+        .collect()
+}
+
+/// True for distributions with parameters that fit estimates, mixtures are kept as written
+fn is_fitted(dist: &Option<DistLink>) -> bool {
+    match dist {
+        Some(DistLink::Counted(d)) | Some(DistLink::Indexed(d)) => !matches!(
+            d,
+            Dist::Constant(_, _, _)
+                | Dist::ExactlyTimes(_)
+                | Dist::Mixture(_)
+                | Dist::Background(_)
+        ),
+        None => false,
+    }
+}
+
+/// True for distributions written as dist in source that fit estimates, as is_fitted
+fn is_fitted_source(dist: &str) -> bool {
+    let name: String = dist
+        .trim_start_matches('~')
+        .trim_start()
+        .chars()
+        .take_while(char::is_ascii_alphabetic)
+        .collect();
+    !matches!(name.to_lowercase().as_str(), "const" | "mix")
+}
+
+/// Sum of the best path log-likelihoods of the lines that match, and the number that do not
+fn corpus_log_likelihood<S: AsRef<str>>(nfa: &[State], corpus: &[S]) -> (f64, usize) {
+    let mut log_likelihood = 0.0;
+    let mut n_unmatched = 0;
+    for line in corpus {
+        match best_path(nfa, &line.as_ref().to_string()) {
+            Some(path) => log_likelihood += path.log_likelihood,
+            None => n_unmatched += 1,
+        }
+    }
+    (log_likelihood, n_unmatched)
+}
+
+/// Values the distribution of each state was evaluated at on the best paths
+fn observe<S: AsRef<str>>(
+    nfa: &[State],
+    corpus: &[S],
+    states: &[usize],
+) -> HashMap<usize, Vec<u64>> {
+    let mut observations: HashMap<usize, Vec<u64>> = HashMap::new();
+    // Branch of its alternation each side of a split chooses, same offsets as the categorical
+    let mut branches: HashMap<(usize, bool), (usize, u64)> = HashMap::new();
+    for idx in states.iter() {
+        let splits = branch_splits(nfa, *idx);
+        for (i, split) in splits.iter().enumerate() {
+            branches.insert((*split, true), (*idx, i as u64 + 1));
+        }
+        if let Some(last) = splits.last() {
+            branches.insert((*last, false), (*idx, splits.len() as u64 + 1));
+        }
+    }
+    for line in corpus {
+        let path = match best_path(nfa, &line.as_ref().to_string()) {
+            Some(path) => path,
+            None => continue,
+        };
+        for step in path.steps {
+            for branch in step.branches {
+                if let Some((idx, x)) = branches.get(&(branch.state, branch.is_left)) {
+                    observations.entry(*idx).or_default().push(*x);
+                }
+            }
+            for repetition in step.repetitions {
+                observations
+                    .entry(repetition.state)
+                    .or_default()
+                    .push(repetition.count);
+            }
+            if let (Kind::Class(_, members) | Kind::FoldedClass(_, members), Kind::Literal(c)) =
+                (&step.kind, &step.token)
+            {
+                if !states.contains(&step.state) {
+                    continue;
+                }
+                let is_folded = matches!(step.kind, Kind::FoldedClass(_, _));
+                let idx = member_index(members, *c, is_folded);
+                // Same offsets as DistLink::pmf_link, the remainder is first
+                let x = match (&nfa[step.state].dist, idx) {
+                    (Some(DistLink::Indexed(Dist::Categorical(_))), None) => 0,
+                    (Some(DistLink::Indexed(Dist::Categorical(_))), Some(i))
+                    | (Some(DistLink::Indexed(Dist::PZipf(_, _, _))), Some(i)) => i as u64 + 1,
+                    (_, Some(i)) => i as u64,
+                    (_, None) => continue,
+                };
+                observations.entry(step.state).or_default().push(x);
+            }
+        }
+    }
+    observations
+}
+
+/// Maximum likelihood estimate of the distribution parameters from values xs
+fn estimate(dist: &Dist, xs: &[u64]) -> Dist {
+    if xs.is_empty() {
+        return dist.clone();
+    }
+    let n = xs.len() as f64;
+    match *dist {
+        Dist::PGeometric(n_min, n_max, _) => {
+            let trials: f64 = xs.iter().map(|x| (x - n_min + 1) as f64).sum();
+            Dist::PGeometric(n_min, n_max, n / trials)
+        }
+        Dist::PBinomial(n_min, n_max, p) if n_max > n_min => {
+            let successes: f64 = xs.iter().map(|x| (x - n_min) as f64).sum();
+            let p_fitted = successes / (n * (n_max - n_min) as f64);
+            Dist::PBinomial(
+                n_min,
+                n_max,
+                if p_fitted.is_finite() { p_fitted } else { p },
+            )
+        }
+        Dist::PBernoulli(n_min, n_max, _) if n_max > n_min => {
+            let successes = xs.iter().filter(|x| **x > n_min).count() as f64;
+            Dist::PBernoulli(n_min, n_max, successes / n)
+        }
+        Dist::PPoisson(n_min, n_max, lambda) => {
+            let mean = xs.iter().map(|x| (x - n_min) as f64).sum::<f64>() / n;
+            Dist::PPoisson(n_min, n_max, if mean > 0.0 { mean } else { lambda })
+        }
+        // The number of successes r is kept, only p is fitted
+        Dist::PNegBinomial(n_min, n_max, r, _) => {
+            let mean = xs.iter().map(|x| (x - n_min) as f64).sum::<f64>() / n;
+            Dist::PNegBinomial(n_min, n_max, r, r / (r + mean))
+        }
+        // The center mu is kept, only the spread around it is fitted
+        Dist::PNormal(n_min, n_max, mu, sigma) => {
+            let variance = xs.iter().map(|x| (*x as f64 - mu).powi(2)).sum::<f64>() / n;
+            Dist::PNormal(
+                n_min,
+                n_max,
+                mu,
+                if variance > 0.0 {
+                    variance.sqrt()
+                } else {
+                    sigma
+                },
+            )
+        }
+        Dist::PLaplace(n_min, n_max, mu, b) => {
+            let deviation = xs.iter().map(|x| (*x as f64 - mu).abs()).sum::<f64>() / n;
+            Dist::PLaplace(
+                n_min,
+                n_max,
+                mu,
+                if deviation > 0.0 { deviation } else { b },
+            )
+        }
+        Dist::Histogram(_) => {
+            let counts = xs.iter().counts();
+            Dist::Histogram(
+                counts
+                    .into_iter()
+                    .map(|(x, k)| (*x, k as f64 / n))
+                    .collect(),
+            )
+        }
+        Dist::PZeta(n_min, n_max, _) => {
+            let sum_ln: f64 = xs.iter().map(|x| (*x as f64).ln()).sum();
+            let log_likelihood = |s: f64| -s * sum_ln - n * zeta_normalizer(s, n_min, n_max).ln();
+            Dist::PZeta(n_min, n_max, maximize(log_likelihood, 1.0 + 1e-6, 20.0))
+        }
+        Dist::PZipf(n_min, n_max, _) => {
+            let sum_ln: f64 = xs.iter().map(|x| (*x as f64).ln()).sum();
+            let ranks = u64::max(1, n_min)..=n_max;
+            let log_likelihood = |s: f64| {
+                let normalizer: f64 = ranks.clone().map(|r| (r as f64).powf(-s)).sum();
+                -s * sum_ln - n * normalizer.ln()
+            };
+            Dist::PZipf(n_min, n_max, maximize(log_likelihood, 0.0, 20.0))
+        }
+        Dist::Categorical(ref prob_mass) => {
+            let counts = xs.iter().counts();
+            let prob_mass = (0..prob_mass.len())
+                .map(|i| *counts.get(&(i as u64)).unwrap_or(&0) as f64 / n)
+                .collect();
+            Dist::Categorical(prob_mass)
+        }
+        _ => dist.clone(),
+    }
+}
+
+/// Argument in [low, high] maximizing a unimodal f, by golden section search
+fn maximize<F: Fn(f64) -> f64>(f: F, mut low: f64, mut high: f64) -> f64 {
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    while high - low > 1e-9 {
+        let a = high - ratio * (high - low);
+        let b = low + ratio * (high - low);
+        match f(a) < f(b) {
+            true => low = a,
+            false => high = b,
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// Source of the distribution of state, as it appears after ~ in a pattern
+fn render(state: &State) -> String {
+    let dist = match &state.dist {
+        Some(DistLink::Counted(d)) | Some(DistLink::Indexed(d)) => d,
+        None => return String::new(),
+    };
+    // Parameters are printed rounded down, so that probabilities never sum past 1
+    let param = |p: f64| format!("{:.6}", (p * 1e6).floor() / 1e6);
+    match (dist, &state.kind) {
+        (Dist::PGeometric(_, _, p), _) => format!("~Geo({})", param(f64::max(*p, 1e-6))),
+        (Dist::PBinomial(_, _, p), _) => format!("~Bin({})", param(*p)),
+        (Dist::PBernoulli(_, _, p), _) => format!("~Ber({})", param(*p)),
+        (Dist::PZipf(_, _, s), _) => format!("~Zipf({})", param(*s)),
+        // Rounded up, so s stays past 1
+        (Dist::PZeta(_, _, s), _) => format!("~Zeta({:.6})", (s * 1e6).ceil() / 1e6),
+        (Dist::Histogram(histogram), _) => {
+            let mut counts = histogram
+                .iter()
+                .map(|(x, p)| format!("{}={}", x, param(*p)));
+            format!("~Hist({})", counts.join(","))
+        }
+        (Dist::PPoisson(_, _, lambda), _) => format!("~Pois({})", param(f64::max(*lambda, 1e-6))),
+        (Dist::PNormal(_, _, _, sigma), _) => format!("~Norm({})", param(f64::max(*sigma, 1e-6))),
+        (Dist::PLaplace(_, _, _, b), _) => format!("~Laplace({})", param(f64::max(*b, 1e-6))),
+        (Dist::PNegBinomial(_, _, r, p), _) => {
+            format!("~NegBin({},{})", r, param(f64::max(*p, 1e-6)))
+        }
+        (Dist::Categorical(prob_mass), Kind::Class(_, members) | Kind::FoldedClass(_, members)) => {
+            // Only characters that are literals can be named, the rest share the remainder
+            let named = members
+                .iter()
+                .zip(prob_mass.iter().skip(1))
+                .filter(|(c, _)| c.is_ascii_alphanumeric() || **c == '-')
+                .map(|(c, p)| format!("{}={}", c, param(*p)));
+            let remainder = format!(".={}", param(prob_mass[0]));
+            format!("~Cat({})", named.chain([remainder]).join(","))
+        }
+        // Every branch is weighed, the remainder is what rounding leaves
+        (Dist::Categorical(prob_mass), Kind::Split) => {
+            format!(
+                "~Cat({})",
+                prob_mass.iter().skip(1).map(|p| param(*p)).join(",")
+            )
+        }
+        _ => dist.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Fit source to lines sampled from the generating source
+    fn fit_sampled(source: &str, generator: &str) -> Fit {
+        let generator = Pattern::new(generator).unwrap();
+        let mut rng = StdRng::seed_from_u64(11);
+        let corpus: Vec<String> = (0..2000).map(|_| generator.sample(&mut rng)).collect();
+        fit(&Pattern::new(source).unwrap(), &corpus, 20).unwrap()
+    }
+
+    /// Parameter of the distribution of state idx
+    fn fitted_param(fit: &Fit, idx: usize) -> f64 {
+        match &fit.pattern.nfa()[idx].dist {
+            Some(DistLink::Counted(d)) | Some(DistLink::Indexed(d)) => match d {
+                Dist::PGeometric(_, _, p)
+                | Dist::PBinomial(_, _, p)
+                | Dist::PBernoulli(_, _, p)
+                | Dist::PZipf(_, _, p)
+                | Dist::PZeta(_, _, p)
+                | Dist::PPoisson(_, _, p)
+                | Dist::PNegBinomial(_, _, _, p)
+                | Dist::PNormal(_, _, _, p)
+                | Dist::PLaplace(_, _, _, p) => *p,
+                _ => panic!("{} has no single parameter", d),
+            },
+            None => panic!("state {} has no distribution", idx),
+        }
+    }
+
+    #[test]
+    fn test_fit_geometric() {
+        let corpus = ["ab", "aaaab"];
+        let pattern = Pattern::new("^a{1~Geo(0.5)}b$").unwrap();
+        let fit = fit(&pattern, &corpus, 20).unwrap();
+
+        // trials of 1 and 4
+        assert_eq!(fit.pattern.as_str(), "^a{1~Geo(0.400000)}b$");
+        assert_relative_eq!(fit.log_likelihood_before, 5. * 0.5_f64.ln());
+        assert_relative_eq!(
+            fit.log_likelihood_after,
+            2. * 0.4_f64.ln() + 3. * 0.6_f64.ln(),
+            max_relative = 1e-5
+        );
+        assert_eq!(fit.n_unmatched, 0);
+    }
+
+    #[test]
+    fn test_fit_short_quantifiers() {
+        let pattern = Pattern::new("^a?~Ber(0.5)b*~Geo(0.5)$").unwrap();
+        let fit = fit(&pattern, &["ab", "abb", "a", "bbbbb"], 20).unwrap();
+
+        // a taken 3 of 4 times, b repeated 8 times over 4 counts
+        assert_eq!(fit.pattern.as_str(), "^a?~Ber(0.750000)b*~Geo(0.333333)$");
+    }
+
+    #[test]
+    fn test_fit_verbose() {
+        let pattern = Pattern::new("(?x) ^ a{1 ~ Geo(0.5)}  # a few a\n (?i)b $").unwrap();
+        let fit = fit(&pattern, &["aB", "aab", "aaaB", "aab"], 20).unwrap();
+
+        assert_eq!(
+            fit.pattern.as_str(),
+            "(?x) ^ a{1 ~Geo(0.500000)}  # a few a\n (?i)b $"
+        );
+    }
+
+    #[test]
+    fn test_fit_mixture() {
+        let pattern = Pattern::new("^a{1~Mix(0.5*Geo(0.5),0.5*Geo(0.1))}b{1~Geo(0.5)}$").unwrap();
+        let fit = fit(&pattern, &["abb", "aabbb", "ab"], 20).unwrap();
+
+        // mixtures are kept as written
+        assert_eq!(
+            fit.pattern.as_str(),
+            "^a{1~Mix(0.5*Geo(0.5),0.5*Geo(0.1))}b{1~Geo(0.500000)}$"
+        );
+    }
+
+    #[test]
+    fn test_fit_unmatched() {
+        let pattern = Pattern::new("^a{1~Geo(0.5)}b$").unwrap();
+        let fit = fit(&pattern, &["ab", "b", "abc"], 20).unwrap();
+
+        assert_eq!(fit.pattern.as_str(), "^a{1~Geo(1.000000)}b$");
+        assert_eq!(fit.n_unmatched, 2);
+    }
+
+    #[test]
+    fn test_fit_sampled() {
+        let fit = fit_sampled("^a{1~Geo(0.5)}b$", "^a{1~Geo(0.2)}b$");
+        assert_relative_eq!(fitted_param(&fit, 1), 0.2, epsilon = 0.02);
+        assert!(fit.log_likelihood_after > fit.log_likelihood_before);
+
+        let fit = fit_sampled("^x{0,8~Bin(0.5)}$", "^x{0,8~Bin(0.7)}$");
+        assert_relative_eq!(fitted_param(&fit, 1), 0.7, epsilon = 0.02);
+
+        let fit = fit_sampled("^[a-j~Zipf(1.0)]$", "^[a-j~Zipf(2.0)]$");
+        assert_relative_eq!(fitted_param(&fit, 1), 2.0, epsilon = 0.1);
+
+        let fit = fit_sampled("^[a-j~Geo(0.5)]$", "^[a-j~Geo(0.3)]$");
+        assert_relative_eq!(fitted_param(&fit, 1), 0.3, epsilon = 0.03);
+
+        let fit = fit_sampled("^a{1~Zeta(2.0)}$", "^a{1~Zeta(3.0)}$");
+        assert_relative_eq!(fitted_param(&fit, 1), 3.0, epsilon = 0.1);
+
+        let fit = fit_sampled("^a{2~Pois(1.0)}$", "^a{2~Pois(4.0)}$");
+        assert_relative_eq!(fitted_param(&fit, 1), 4.0, epsilon = 0.15);
+
+        let fit = fit_sampled("^a{8~Norm(1.0)}$", "^a{8~Norm(2.5)}$");
+        assert_relative_eq!(fitted_param(&fit, 1), 2.5, epsilon = 0.15);
+
+        let fit = fit_sampled("^a{0~NegBin(3,0.5)}$", "^a{0~NegBin(3,0.3)}$");
+        assert_relative_eq!(fitted_param(&fit, 1), 0.3, epsilon = 0.02);
+    }
+
+    #[test]
+    fn test_fit_histogram() {
+        let corpus = ["aab", "aab", "aaaab", "aaaaab"];
+        let pattern = Pattern::new("^a{~Hist(2=0.5,4=0.5)}b$").unwrap();
+        let fit = fit(&pattern, &corpus, 20).unwrap();
+
+        // the line with 5 repetitions does not match
+        assert_eq!(fit.n_unmatched, 1);
+        assert_eq!(fit.pattern.as_str(), "^a{~Hist(2=0.666666,4=0.333333)}b$");
+    }
+
+    #[test]
+    fn test_fit_categorical() {
+        let fit = fit_sampled("^[abc~Cat]$", "^[abc~Cat(a=0.6,b=0.3)]$");
+        let weights: Vec<f64> = fit
+            .pattern
+            .as_str()
+            .split(['=', ',', ')'])
+            .filter_map(|s| s.parse().ok())
+            .collect();
+        assert_eq!(weights.len(), 4);
+        assert_relative_eq!(weights[0], 0.6, epsilon = 0.03);
+        assert_relative_eq!(weights[1], 0.3, epsilon = 0.03);
+        assert_relative_eq!(weights[2], 0.1, epsilon = 0.03);
+        assert_eq!(weights[3], 0.0);
+    }
+
+    #[test]
+    fn test_fit_alternation() {
+        let pattern = Pattern::new("^(a|b)~Cat(0.5,0.5)$").unwrap();
+        let fit = fit(&pattern, &["a", "b", "b", "b"], 20).unwrap();
+        assert_eq!(fit.pattern.as_str(), "^(a|b)~Cat(0.250000,0.750000)$");
+        assert_relative_eq!(
+            fit.log_likelihood_after,
+            0.25_f64.ln() + 3. * 0.75_f64.ln(),
+            max_relative = 1e-5
+        );
+
+        // a chain of splits, with a weighted alternation as its last branch
+        let source = "^(a|b{1~Geo(0.5)}|(?:c|d)~Cat(0.5,0.5))~Cat$";
+        let pattern = Pattern::new(source).unwrap();
+        let corpus = ["a", "bb", "c", "c", "c", "d"];
+        let chain = super::fit(&pattern, &corpus, 20).unwrap();
+        assert_eq!(
+            chain.pattern.as_str(),
+            "^(a|b{1~Geo(0.500000)}|(?:c|d)~Cat(0.750000,0.250000))~Cat(0.166666,0.166666,0.666666)$"
+        );
+    }
+
+    #[test]
+    fn test_fit_nested() {
+        // distributions appear in source in a different order than their states
+        let source = "^([ab~Cat(a=0.5)]{1~Geo(0.5)}c)+$";
+        let pattern = Pattern::new(source).unwrap();
+        assert_eq!(fitted_states(pattern.ast()), vec![4, 3]);
+
+        let fit = fit(&pattern, &["aaac", "abcbc"], 20).unwrap();
+        assert!(fit.log_likelihood_after > fit.log_likelihood_before);
+        assert!(fit.pattern.as_str().starts_with("^([ab~Cat(a="));
+        assert!(fit.pattern.as_str().ends_with("c)+$"));
+    }
+
+    #[test]
+    fn test_fit_keeps_constant() {
+        let pattern = Pattern::new("^a{2,3~Const(0.5)}[xy~Geo(0.5)]$").unwrap();
+        let fit = fit(&pattern, &["aax", "aaax", "aay"], 20).unwrap();
+        assert!(fit
+            .pattern
+            .as_str()
+            .starts_with("^a{2,3~Const(0.5)}[xy~Geo(0."));
+    }
+}
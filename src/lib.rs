@@ -0,0 +1,772 @@
+//! Probabilistic regular expressions.
+//!
+//! A pattern is compiled into a NFA whose quantifiers and character classes
+//! may carry distributions, so that matching an input yields a likelihood
+//! rather than a plain yes or no.
+//!
+//! ```
+//! use pregex::Pattern;
+//!
+//! let pattern = Pattern::new("^a{2~Geo(0.5)}$").unwrap();
+//! assert_eq!(pattern.likelihood("aaa"), Some(0.25));
+//! assert!(!pattern.is_match("a"));
+//! ```
+
+pub mod ast;
+pub mod background;
+mod charclass;
+pub mod distribution;
+pub mod error;
+pub mod find;
+pub mod fit;
+pub mod nfa;
+pub mod parser;
+pub mod path;
+mod pattern;
+pub mod regex;
+pub mod regex_state;
+pub mod sample;
+pub mod semiring;
+mod visualization;
+
+pub use crate::background::Background;
+pub use crate::error::{CompileError, Span};
+pub use crate::find::Match;
+pub use crate::fit::Fit;
+pub use crate::path::{Capture, Path};
+pub use crate::pattern::Pattern;
+pub use crate::semiring::Semiring;
+
+use std::error::Error;
+
+pub type Result<T> = ::std::result::Result<T, Box<dyn Error>>;
+
+/// Compile a pattern source into a NFA
+pub fn compile(source: &str) -> ::std::result::Result<Vec<nfa::State>, CompileError> {
+    Ok(nfa::asts_to_nfa(parser::parse(source)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+    use regex::match_likelihood;
+
+    #[test]
+    fn test_basic() {
+        let nfa = compile("abc").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
+        assert_eq!(
+            match_likelihood(&nfa, &"abcd".to_string(), false),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn test_basic_anchor() {
+        let nfa = compile("^abc$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"abcd".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_literal_escape() {
+        let nfa = compile(r"^a\\db$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"a0b".to_string(), false), None);
+        assert_eq!(
+            match_likelihood(&nfa, &"a\\db".to_string(), false),
+            Some(1.0)
+        );
+        assert_eq!(match_likelihood(&nfa, &"a\\ddb".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_quantifier_zero() {
+        let nfa = compile("^ab{0}$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_quantifier_zero_constant() {
+        let nfa = compile("^ab{0~Const(0.5)}$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), None);
+
+        let nfa = compile("^a\\d{0~Const(0.5)}$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"a0".to_string(), false), None);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_quantifier_geo() {
+        let nfa = compile("^a{5~Geo(0.5)}$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"aaaa".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"aaaaa".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"aaaaaa".to_string(), false), Some(0.25));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_quantifier_zipf() {
+        let nfa = compile("^a{2~Zipf(1.0)}$").unwrap();
+        let harmonic_number_2 = 3. / 2.;
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some((1. / 1.) / harmonic_number_2));
+        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), Some((1. / 2.) / harmonic_number_2));
+        assert_eq!(match_likelihood(&nfa, &"aaa".to_string(), false), Some((1. / 3.) / harmonic_number_2));
+    }
+
+    #[test]
+    fn test_quantifier_pois() {
+        // e^-1 / k!, offset by 2
+        let nfa = compile("^a{2~Pois(1.0)}$").unwrap();
+        let e = (-1.0_f64).exp();
+
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), None);
+        assert_relative_eq!(match_likelihood(&nfa, &"aa".to_string(), false).unwrap(), e);
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"aaaa".to_string(), false).unwrap(),
+            e / 2.
+        );
+    }
+
+    #[test]
+    fn test_quantifier_negbin() {
+        // failures before the second success, (k + 1) p^2 (1 - p)^k
+        let nfa = compile("^a{1~NegBin(2,0.5)}$").unwrap();
+        let p = |input: &str| match_likelihood(&nfa, &input.to_string(), false).unwrap();
+
+        assert_relative_eq!(p("a"), 0.25, epsilon = 1e-12);
+        assert_relative_eq!(p("aa"), 0.25, epsilon = 1e-12);
+        assert_relative_eq!(p("aaa"), 0.1875, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_quantifier_norm() {
+        // about 8 repetitions, with fewer allowed
+        let nfa = compile("^a{8~Norm(1.5)}$").unwrap();
+        let p = |n: usize| match_likelihood(&nfa, &"a".repeat(n), false).unwrap();
+
+        assert!(p(8) > p(7) && p(7) > p(5));
+        assert_relative_eq!(p(7), p(9));
+
+        // within a range, centered on its middle
+        let nfa = compile("^a{6,10~Laplace(1.0)}$").unwrap();
+        let p = |n: usize| match_likelihood(&nfa, &"a".repeat(n), false);
+
+        assert_eq!(p(5), None);
+        assert_relative_eq!(p(7).unwrap(), p(9).unwrap());
+        assert_eq!(p(11), None);
+    }
+
+    #[test]
+    fn test_quantifier_mixture() {
+        // usually 2 or usually 10
+        let nfa = compile("^a{2~Mix(0.5*Norm(0.5),0.5*Pois(8.0))}$").unwrap();
+        let p = |n: usize| match_likelihood(&nfa, &"a".repeat(n), false).unwrap();
+        let nfa_pois = compile("^a{2~Pois(8.0)}$").unwrap();
+        let p_pois = |n: usize| match_likelihood(&nfa_pois, &"a".repeat(n), false).unwrap();
+
+        assert_relative_eq!(p(10), 0.5 * p_pois(10));
+        assert!(p(2) > p(10) && p(10) > p(6));
+    }
+
+    #[test]
+    fn test_quantifier_histogram() {
+        let nfa = compile("^a{~Hist(3=0.1,4=0.6,5=0.3)}b$").unwrap();
+        let p = |n: usize| match_likelihood(&nfa, &format!("{}b", "a".repeat(n)), false);
+
+        assert_eq!(p(2), None);
+        assert_eq!(p(3), Some(0.1));
+        assert_eq!(p(4), Some(0.6));
+        assert_eq!(p(6), None);
+
+        // counts outside the bounds are dropped and the rest renormalized
+        let nfa = compile("^a{2,3~Hist(2=0.5,4=0.5)}$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), Some(1.0));
+        let nfa = compile("^a{3~Hist(2=0.25,3=0.25,4=0.5)}$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), None);
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"aaaa".to_string(), false).unwrap(),
+            2.0 / 3.0
+        );
+    }
+
+    #[test]
+    fn test_quantifier_zeta() {
+        // unbounded, 1 / k^2 normalized by pi^2 / 6
+        let nfa = compile("^a{1,~Zeta(2.0)}$").unwrap();
+        let zeta_2 = std::f64::consts::PI.powi(2) / 6.0;
+        let p = |n: usize| match_likelihood(&nfa, &"a".repeat(n), false).unwrap();
+
+        assert_relative_eq!(p(1), 1.0 / zeta_2, epsilon = 1e-12);
+        assert_relative_eq!(p(200), 1.0 / 40000.0 / zeta_2, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_quantifier_range() {
+        let nfa = compile("^a{2,4}$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"aaa".to_string(), false), Some(1.0));
+        assert_eq!(
+            match_likelihood(&nfa, &"aaaa".to_string(), false),
+            Some(1.0)
+        );
+        assert_eq!(match_likelihood(&nfa, &"aaaaa".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_quantifier_range_unbounded() {
+        let nfa = compile("^a{2,}b$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"aab".to_string(), false), Some(1.0));
+        assert_eq!(
+            match_likelihood(&nfa, &"aaaaaab".to_string(), false),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_quantifier_range_geo() {
+        // geometric truncated to 2..=4, so mass is 1 - 0.5^3
+        let nfa = compile("^a{2,4~Geo(0.5)}$").unwrap();
+        let mass = 0.875;
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), Some(0.5 / mass));
+        assert_eq!(match_likelihood(&nfa, &"aaa".to_string(), false), Some(0.25 / mass));
+        assert_eq!(match_likelihood(&nfa, &"aaaa".to_string(), false), Some(0.125 / mass));
+        assert_eq!(match_likelihood(&nfa, &"aaaaa".to_string(), false), None);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_quantifier_range_bin() {
+        // binomial with 2 trials offset by 1
+        let nfa = compile("^a{1,3~Bin(0.5)}$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.25));
+        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"aaa".to_string(), false), Some(0.25));
+        assert_eq!(match_likelihood(&nfa, &"aaaa".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_class() {
+        let nfa = compile("^a[bc]c$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"ac".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"acc".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"adc".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"abcc".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_flags() {
+        // the distribution applies to the folded symbol
+        let nfa = compile("^(?i)[ab~Cat(a=0.7,b=0.3)]x$").unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"AX".to_string(), false).unwrap(),
+            0.7
+        );
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"bx".to_string(), false).unwrap(),
+            0.3
+        );
+        let nfa = compile("^(?i:a)b$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"Ab".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"AB".to_string(), false), None);
+
+        let nfa = compile("^a.b$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"a\nb".to_string(), false), None);
+        let nfa = compile("^(?s)a.b$").unwrap();
+        assert_eq!(
+            match_likelihood(&nfa, &"a\nb".to_string(), false),
+            Some(1.0)
+        );
+
+        let nfa = compile(
+            "(?x)
+            ^ [ab ~ Cat( a=0.5, b=0.5 )]  # first letter
+              c{1 ~ Geo(0.5)}             # then a few c
+            $",
+        )
+        .unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"bcc".to_string(), false).unwrap(),
+            0.125
+        );
+    }
+
+    #[test]
+    fn test_unicode() {
+        let nfa = compile("^caf\\p{Ll} \\P{L}+ 日本$").unwrap();
+        assert_eq!(
+            match_likelihood(&nfa, &"café 42! 日本".to_string(), false),
+            Some(1.0)
+        );
+        assert_eq!(
+            match_likelihood(&nfa, &"cafÉ 42 日本".to_string(), false),
+            None
+        );
+        assert_eq!(
+            match_likelihood(&nfa, &"café 4x 日本".to_string(), false),
+            None
+        );
+
+        // indexed over the digits in code point order, ٣ is the 3rd after 0 to 9
+        let nfa = compile("^\\p{Nd}~Geo(0.5)$").unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"1".to_string(), false).unwrap(),
+            0.25
+        );
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"٣".to_string(), false).unwrap(),
+            0.5_f64.powi(14)
+        );
+    }
+
+    #[test]
+    fn test_named_classes() {
+        let nfa = compile("^\\w+\\s\\W\\D[[:punct:][:upper:]]$").unwrap();
+        assert_eq!(
+            match_likelihood(&nfa, &"a_1\t!xA".to_string(), false),
+            Some(1.0)
+        );
+        assert_eq!(
+            match_likelihood(&nfa, &"a_1\t!x;".to_string(), false),
+            Some(1.0)
+        );
+        assert_eq!(match_likelihood(&nfa, &"a_1\tx!A".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"a_1\t!5A".to_string(), false), None);
+
+        // indexed in code point order, B is the 12th member of \w
+        let nfa = compile("^\\w~Geo(0.5)\\x20\\u{e9}$").unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"B é".to_string(), false).unwrap(),
+            0.5_f64.powi(12)
+        );
+    }
+
+    #[test]
+    fn test_named_classes_negated_within_class() {
+        let is_match = |source: &str, input: &str| {
+            let nfa = compile(source).unwrap();
+            match_likelihood(&nfa, &input.to_string(), false).is_some()
+        };
+        assert!(is_match("^[\\W]+$", &"#é ".repeat(1000)));
+        assert!(!is_match("^[\\W]+$", "#a"));
+        assert!(is_match("^[\\Da]+$", "xa#"));
+        assert!(!is_match("^[\\Da]+$", "x1"));
+
+        // members and their other case are united before the class is negated
+        assert!(is_match("^(?i)[\\Wk]$", "K"));
+        assert!(!is_match("^(?i)[^\\Wk]$", "K"));
+        assert!(is_match("^(?i)[^\\Wk]$", "j"));
+        assert!(is_match("^(?i)[\\Da]+$", &"xA".repeat(1000)));
+    }
+
+    #[test]
+    fn test_class_range() {
+        let nfa = compile("^[a-zA-Z0-9_]+$").unwrap();
+
+        assert_eq!(
+            match_likelihood(&nfa, &"a_Z9".to_string(), false),
+            Some(1.0)
+        );
+        assert_eq!(match_likelihood(&nfa, &"a-b".to_string(), false), None);
+
+        let nfa = compile("^[^a-c]$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), Some(1.0));
+    }
+
+    #[test]
+    fn test_class_range_geo() {
+        let nfa = compile("^[a-c~Geo(0.5)]$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.25));
+        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(0.125));
+        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_class_negate() {
+        let nfa = compile("^a[^bc]c$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"ac".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"adc".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"aec".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"adcc".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_class_categorical() {
+        let nfa = compile("^[abc~Cat(a=0.3,b=0.3)]$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.3));
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.3));
+        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(0.4));
+        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_class_categorical_symmetry_1() {
+        let nfa = compile("^[abc~Const]$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_class_categorical_negate_symmetry_1() {
+        let nfa = compile("^[^abc~Const]$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), Some(1.0));
+    }
+
+    #[test]
+    fn test_class_categorical_negate_symmetry_2() {
+        let nfa = compile("^[^abc~Const(0.5)]$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), Some(1.0));
+    }
+
+    #[test]
+    fn test_class_negate_categorical() {
+        let nfa = compile("^[^abc~Cat(a=0.3,b=0.3)]$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.3));
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.3));
+        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), Some(0.4));
+    }
+
+    #[test]
+    fn test_class_negate_categorical_symmetry() {
+        let nfa1 = compile("^[^ab~Cat]$").unwrap();
+        let nfa2 = compile("^[ab~Cat]$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa1, &"a".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa2, &"a".to_string(), false), Some(0.5));
+
+        assert_eq!(match_likelihood(&nfa1, &"b".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa2, &"b".to_string(), false), Some(0.5));
+
+        assert_eq!(match_likelihood(&nfa1, &"d".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa2, &"d".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_class_negate_categorical_symmetry2() {
+        let nfa1 = compile("^[^ab~Cat]$").unwrap();
+        let nfa2 = compile("^[ab~Cat(.=1.0)]$").unwrap();
+        assert_eq!(match_likelihood(&nfa1, &"a".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa2, &"a".to_string(), false), None);
+
+        assert_eq!(match_likelihood(&nfa1, &"b".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa2, &"b".to_string(), false), None);
+
+        assert_eq!(match_likelihood(&nfa1, &"c".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa2, &"c".to_string(), false), Some(1.0));
+    }
+
+    #[test]
+    fn test_class_dirichlet() {
+        let nfa = compile("^[abc~Dir(a=4,b=1)]$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.625));
+        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(0.125));
+        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_bare_class_dists() {
+        let nfa = compile("^\\d~Cat(0=0.5)x$").unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"0x".to_string(), false).unwrap(),
+            0.5
+        );
+        assert_eq!(match_likelihood(&nfa, &"ax".to_string(), false), None);
+
+        let nfa = compile("^.~Cat(a=0.5,b=0.3)$").unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"a".to_string(), false).unwrap(),
+            0.5
+        );
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"b".to_string(), false).unwrap(),
+            0.3
+        );
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"z".to_string(), false).unwrap(),
+            0.2
+        );
+
+        // like ., newline is only matched with (?s)
+        assert_eq!(match_likelihood(&nfa, &"\n".to_string(), false), None);
+        let nfa = compile("(?s)^.~Cat(a=0.5,b=0.3)$").unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"\n".to_string(), false).unwrap(),
+            0.2
+        );
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_class_zipf() {
+        // n = 2, 1st is 2/3 and the 2nd is 1/3
+        // s = 1.0, normalizer is the (non-generalized) harmonic number
+        let nfa = compile("^[ab~Zipf(1.0)]$").unwrap();
+        let harmonic_number_2 = 3. / 2.;
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some((1. / 1.) / harmonic_number_2));
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some((1. / 2.) / harmonic_number_2));
+        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_class_geo() {
+        let nfa = compile("^[abc~Geo(0.5)]$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.25));
+        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(0.125));
+    }
+
+    #[test]
+    fn test_class_ber() {
+        let nfa = compile("^[abc~Ber(0.5)]$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_class_bin() {
+        let nfa = compile("^[abc~Bin(0.5)]$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.25));
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(0.25));
+    }
+
+    #[test]
+    fn test_class_nested_geo() {
+        let nfa = compile(r"^[a\d~Geo(0.5)]$").unwrap();
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
+        assert_eq!(match_likelihood(&nfa, &"0".to_string(), false), Some(0.25));
+        assert_eq!(match_likelihood(&nfa, &"1".to_string(), false), Some(0.125));
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_dot() {
+        let nfa = compile("^a.c$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"abcd".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_dot_quantifier_plus() {
+        let nfa = compile("^a.+c$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
+        assert_eq!(
+            match_likelihood(&nfa, &"abbc".to_string(), false),
+            Some(1.0)
+        );
+        assert_eq!(match_likelihood(&nfa, &"abcd".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_dot_quantifier_question() {
+        let nfa = compile("^a.?c$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"ac".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"abbc".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"abcd".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_dot_quantifier_exact() {
+        let nfa = compile("^a.{2}c$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"ac".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), None);
+        assert_eq!(
+            match_likelihood(&nfa, &"abbc".to_string(), false),
+            Some(1.0)
+        );
+        assert_eq!(match_likelihood(&nfa, &"abbbc".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"abcd".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_short_class() {
+        let nfa = compile("^a\\dc$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"ac".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"a1c".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"a2c".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"a12c".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_quantifier_plus_needs_one() {
+        let nfa = compile("^xa+y$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"xy".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"xay".to_string(), false), Some(1.0));
+        assert_eq!(
+            match_likelihood(&nfa, &"xaay".to_string(), false),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn test_dot_not_end() {
+        let nfa = compile("a.").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), Some(1.0));
+    }
+
+    #[test]
+    fn test_nested_quantifiers() {
+        let nfa = compile("^(a*)*b$").unwrap();
+
+        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"aab".to_string(), false), Some(1.0));
+        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_nested_exact_quantifiers() {
+        // the inner count starts over on every outer repetition
+        let nfa = compile("^(a{2}b){2}$").unwrap();
+
+        assert_eq!(
+            match_likelihood(&nfa, &"aabaab".to_string(), false),
+            Some(1.0)
+        );
+        assert_eq!(match_likelihood(&nfa, &"aabab".to_string(), false), None);
+        assert_eq!(match_likelihood(&nfa, &"aabaaab".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_alternation_weighted() {
+        let nfa = compile("^(cat|dog|bird)~Cat(0.5,0.3,0.2)$").unwrap();
+        for (input, p) in [("cat", 0.5), ("dog", 0.3), ("bird", 0.2)] {
+            assert_relative_eq!(
+                match_likelihood(&nfa, &input.to_string(), false).unwrap(),
+                p
+            );
+        }
+        assert_eq!(match_likelihood(&nfa, &"cow".to_string(), false), None);
+
+        // branches without a weight share the rest
+        let nfa = compile("^(?:a|b|c)~Cat(0.4)$").unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"c".to_string(), false).unwrap(),
+            0.3
+        );
+    }
+
+    #[test]
+    fn test_alternation_weighted_nested() {
+        // the inner alternation is a single branch of the outer one
+        let nfa = compile("^(a|(?:b|c)~Cat(0.5,0.5))~Cat(0.2,0.8)$").unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"a".to_string(), false).unwrap(),
+            0.2
+        );
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"c".to_string(), false).unwrap(),
+            0.4
+        );
+
+        // unweighted groups inside a weighted alternation are branches of their own
+        let nfa = compile("^(a|(b|c))~Cat(0.2,0.8)$").unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"c".to_string(), false).unwrap(),
+            0.8
+        );
+
+        // also when the group does not capture, so the branches still sum to 1
+        let nfa = compile("^(a|(?:b|c))~Cat(0.2,0.8)$").unwrap();
+        let p = |input: &str| match_likelihood(&nfa, &input.to_string(), false).unwrap();
+        assert_relative_eq!(p("a") + p("b"), 1.0);
+        assert_relative_eq!(p("b"), 0.8);
+        assert_relative_eq!(p("c"), 0.8);
+    }
+
+    #[test]
+    fn test_short_quantifier_dists() {
+        let nfa = compile("^a?~Ber(0.8)b$").unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"ab".to_string(), false).unwrap(),
+            0.8
+        );
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"b".to_string(), false).unwrap(),
+            0.2
+        );
+
+        let nfa = compile("^a*~Geo(0.4)$").unwrap();
+        assert_relative_eq!(match_likelihood(&nfa, &"".to_string(), false).unwrap(), 0.4);
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"aa".to_string(), false).unwrap(),
+            0.144
+        );
+
+        // + counts from one, as {1,} does
+        let nfa = compile("^(xy)+~Pois(2.0)$").unwrap();
+        let plus = compile("^(xy){1,~Pois(2.0)}$").unwrap();
+        for s in ["xy", "xyxy", "xyxyxy"] {
+            assert_relative_eq!(
+                match_likelihood(&nfa, &s.to_string(), false).unwrap(),
+                match_likelihood(&plus, &s.to_string(), false).unwrap()
+            );
+        }
+        assert_eq!(match_likelihood(&nfa, &"".to_string(), false), None);
+    }
+
+    #[test]
+    fn test_alternation_weighted_quantified() {
+        let nfa = compile("^(a|b)~Cat(0.2,0.8){2}$").unwrap();
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"ab".to_string(), false).unwrap(),
+            0.16
+        );
+        assert_relative_eq!(
+            match_likelihood(&nfa, &"bb".to_string(), false).unwrap(),
+            0.64
+        );
+    }
+}
@@ -1,51 +1,154 @@
-#![allow(unused_imports)]
-#![feature(hash_drain_filter)]
-
 use {
     clap::Parser,
-    itertools::Itertools,
-    log::Level,
-    statrs::distribution::{Bernoulli, Binomial, Discrete, Geometric},
-    std::error::Error,
+    pregex::{background, Background, Pattern, Result},
+    rand::{rngs::StdRng, SeedableRng},
     std::io::{self, prelude::*, BufReader, Cursor, Read},
     std::process::exit,
 };
 
-mod ast;
-mod charclass;
 mod cli;
-mod distribution;
-mod nfa;
-mod parser;
-mod regex;
-mod regex_state;
-mod visualization;
-
-use crate::cli::Config;
 
-pub type Result<T> = ::std::result::Result<T, Box<dyn Error>>;
+use crate::cli::{Command, Config, FitConfig, FreqsConfig, SampleConfig, Score};
 
 fn main() -> Result<()> {
     let config = Config::parse();
     env_logger::init();
-    let nfa = compile(&config.pattern)?;
+    match &config.command {
+        Some(Command::Sample(sample_config)) => return sample(sample_config),
+        Some(Command::Fit(fit_config)) => return fit(fit_config),
+        Some(Command::Freqs(freqs_config)) => return freqs(freqs_config),
+        None => {}
+    }
+    let source = config
+        .pattern
+        .as_deref()
+        .expect("pattern to have been specified");
+    let mut pattern = compile(source).with_semiring(config.semiring);
+    if let Some(path) = &config.background {
+        pattern = pattern.with_background(&read_background(path)?);
+    }
     let reader = input_reader(&config)?;
 
     for line in reader.lines() {
-        match line {
-            Ok(input) => match regex::match_likelihood(&nfa, &input, config.visualize) {
-                Some(p) => println!("{:.5}\t{}", p, input),
-                None => {}
-            },
-            Err(e) => return Err(e.into()),
+        let input = line?;
+        if config.visualize {
+            pattern.visualize(&input);
+        }
+        if config.find {
+            for m in pattern.find_iter(&input) {
+                let matched = &input[m.range()];
+                let score = config
+                    .score
+                    .of_log_likelihood(m.log_likelihood, matched.chars().count());
+                println!("{}\t{}\t{:.5}\t{}", m.start, m.end, score, matched);
+            }
+            continue;
+        }
+        let score = match config.score {
+            Score::Prob => pattern.likelihood(&input),
+            score => pattern
+                .log_likelihood(&input)
+                .map(|ll| score.of_log_likelihood(ll, input.chars().count())),
+        };
+        if let Some(score) = score {
+            println!("{:.5}\t{}", score, input);
+            if config.explain_path || config.captures {
+                if let Some(path) = pattern.best_path(&input) {
+                    if config.explain_path {
+                        println!("{}", path);
+                    }
+                    if config.captures {
+                        for capture in path.captures() {
+                            println!("{}", capture);
+                        }
+                    }
+                }
+            }
         }
     }
 
     Ok(())
 }
 
-pub fn compile(source: &str) -> Result<Vec<nfa::State>> {
-    Ok(nfa::asts_to_nfa(parser::parse(source)?))
+/// Print n random strings drawn from the pattern
+fn sample(config: &SampleConfig) -> Result<()> {
+    let mut pattern = compile(&config.pattern);
+    if let Some(path) = &config.background {
+        pattern = pattern.with_background(&read_background(path)?);
+    }
+    let mut rng = match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    for _ in 0..config.n {
+        println!("{}", pattern.sample(&mut rng));
+    }
+    Ok(())
+}
+
+/// Print the pattern with parameters fitted to the corpus and its log-likelihood
+fn fit(config: &FitConfig) -> Result<()> {
+    let pattern = compile(&config.pattern);
+    let corpus = read_corpus(&config.input_file)?;
+    let fit = match pattern.fit(&corpus, config.iterations) {
+        Ok(fit) => fit,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
+        }
+    };
+
+    println!("{}", fit.pattern);
+    println!("log-likelihood before\t{:.5}", fit.log_likelihood_before);
+    println!("log-likelihood after\t{:.5}", fit.log_likelihood_after);
+    if fit.n_unmatched > 0 {
+        eprintln!("{} lines did not match and were left out", fit.n_unmatched);
+    }
+    Ok(())
+}
+
+/// Print the number of times each character occurs in the corpus
+fn freqs(config: &FreqsConfig) -> Result<()> {
+    let corpus = read_corpus(&config.input_file)?;
+    for (c, n) in background::count_chars(&corpus) {
+        println!("{}\t{}", background::escape(c), n);
+    }
+    Ok(())
+}
+
+/// Lines of a corpus file, or of stdin for -
+fn read_corpus(input_file: &str) -> Result<Vec<String>> {
+    use std::fs::File;
+
+    let reader: Box<dyn Read> = match input_file {
+        "-" => Box::new(io::stdin()),
+        input_file => Box::new(File::open(input_file)?),
+    };
+    Ok(BufReader::new(reader)
+        .lines()
+        .collect::<io::Result<Vec<_>>>()?)
+}
+
+/// Read a character frequency table, or exit with the line that is malformed
+fn read_background(path: &str) -> Result<Background> {
+    match std::fs::read_to_string(path)?.parse() {
+        Ok(background) => Ok(background),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            exit(2);
+        }
+    }
+}
+
+/// Compile the pattern, or exit with the error pointing into source
+fn compile(source: &str) -> Pattern {
+    match Pattern::new(source) {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            eprintln!("{}", e.annotate(source));
+            exit(2);
+        }
+    }
 }
 
 /// Get input reader based on config
@@ -72,287 +175,3 @@ fn input_reader(config: &cli::Config) -> Result<BufReader<Box<dyn Read>>> {
 
     Ok(BufReader::new(reader))
 }
-
-#[cfg(test)]
-mod test {
-    use super::*;
-    use approx::assert_relative_eq;
-    use regex::match_likelihood;
-
-    #[test]
-    fn test_basic() {
-        let nfa = compile("abc").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
-        assert_eq!(
-            match_likelihood(&nfa, &"abcd".to_string(), false),
-            Some(1.0)
-        );
-    }
-
-    #[test]
-    fn test_basic_anchor() {
-        let nfa = compile("^abc$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"abcd".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_literal_escape() {
-        let nfa = compile(r"^a\\db$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"a0b".to_string(), false), None);
-        assert_eq!(
-            match_likelihood(&nfa, &"a\\db".to_string(), false),
-            Some(1.0)
-        );
-        assert_eq!(match_likelihood(&nfa, &"a\\ddb".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_quantifier_zero() {
-        let nfa = compile("^ab{0}$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_quantifier_zero_constant() {
-        let nfa = compile("^ab{0~Const(0.5)}$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
-        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), None);
-
-        let nfa = compile("^a\\d{0~Const(0.5)}$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
-        assert_eq!(match_likelihood(&nfa, &"a0".to_string(), false), None);
-    }
-
-    #[test]
-    #[rustfmt::skip]
-    fn test_quantifier_geo() {
-        let nfa = compile("^a{5~Geo(0.5)}$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"aaaa".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"aaaaa".to_string(), false), Some(0.5));
-        assert_eq!(match_likelihood(&nfa, &"aaaaaa".to_string(), false), Some(0.25));
-    }
-
-    #[test]
-    #[rustfmt::skip]
-    fn test_quantifier_zipf() {
-        let nfa = compile("^a{2~Zipf(1.0)}$").unwrap();
-        let harmonic_number_2 = 3. / 2.;
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some((1. / 1.) / harmonic_number_2));
-        assert_eq!(match_likelihood(&nfa, &"aa".to_string(), false), Some((1. / 2.) / harmonic_number_2));
-        assert_eq!(match_likelihood(&nfa, &"aaa".to_string(), false), Some((1. / 3.) / harmonic_number_2));
-    }
-
-    #[test]
-    fn test_class() {
-        let nfa = compile("^a[bc]c$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"ac".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"acc".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"adc".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"abcc".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_class_negate() {
-        let nfa = compile("^a[^bc]c$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"ac".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"adc".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"aec".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"adcc".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_class_categorical() {
-        let nfa = compile("^[abc~Cat(a=0.3,b=0.3)]$").unwrap();
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.3));
-        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.3));
-        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(0.4));
-        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_class_categorical_symmetry_1() {
-        let nfa = compile("^[abc~Const]$").unwrap();
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_class_categorical_negate_symmetry_1() {
-        let nfa = compile("^[^abc~Const]$").unwrap();
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), Some(1.0));
-    }
-
-    #[test]
-    fn test_class_categorical_negate_symmetry_2() {
-        let nfa = compile("^[^abc~Const(0.5)]$").unwrap();
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
-        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.5));
-        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(0.5));
-        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), Some(1.0));
-    }
-
-    #[test]
-    fn test_class_negate_categorical() {
-        let nfa = compile("^[^abc~Cat(a=0.3,b=0.3)]$").unwrap();
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.3));
-        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.3));
-        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"d".to_string(), false), Some(0.4));
-    }
-
-    #[test]
-    fn test_class_negate_categorical_symmetry() {
-        let nfa1 = compile("^[^ab~Cat]$").unwrap();
-        let nfa2 = compile("^[ab~Cat]$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa1, &"a".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa2, &"a".to_string(), false), Some(0.5));
-
-        assert_eq!(match_likelihood(&nfa1, &"b".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa2, &"b".to_string(), false), Some(0.5));
-
-        assert_eq!(match_likelihood(&nfa1, &"d".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa2, &"d".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_class_negate_categorical_symmetry2() {
-        let nfa1 = compile("^[^ab~Cat]$").unwrap();
-        let nfa2 = compile("^[ab~Cat(.=1.0)]$").unwrap();
-        assert_eq!(match_likelihood(&nfa1, &"a".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa2, &"a".to_string(), false), None);
-
-        assert_eq!(match_likelihood(&nfa1, &"b".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa2, &"b".to_string(), false), None);
-
-        assert_eq!(match_likelihood(&nfa1, &"c".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa2, &"c".to_string(), false), Some(1.0));
-    }
-
-    #[test]
-    #[rustfmt::skip]
-    fn test_class_zipf() {
-        // n = 2, 1st is 2/3 and the 2nd is 1/3
-        // s = 1.0, normalizer is the (non-generalized) harmonic number
-        let nfa = compile("^[ab~Zipf(1.0)]$").unwrap();
-        let harmonic_number_2 = 3. / 2.;
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some((1. / 1.) / harmonic_number_2));
-        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some((1. / 2.) / harmonic_number_2));
-        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_class_geo() {
-        let nfa = compile("^[abc~Geo(0.5)]$").unwrap();
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
-        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.25));
-        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(0.125));
-    }
-
-    #[test]
-    fn test_class_ber() {
-        let nfa = compile("^[abc~Ber(0.5)]$").unwrap();
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
-        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.5));
-        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_class_bin() {
-        let nfa = compile("^[abc~Bin(0.5)]$").unwrap();
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.25));
-        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), Some(0.5));
-        assert_eq!(match_likelihood(&nfa, &"c".to_string(), false), Some(0.25));
-    }
-
-    #[test]
-    fn test_class_nested_geo() {
-        let nfa = compile(r"^[a\d~Geo(0.5)]$").unwrap();
-        assert_eq!(match_likelihood(&nfa, &"a".to_string(), false), Some(0.5));
-        assert_eq!(match_likelihood(&nfa, &"0".to_string(), false), Some(0.25));
-        assert_eq!(match_likelihood(&nfa, &"1".to_string(), false), Some(0.125));
-        assert_eq!(match_likelihood(&nfa, &"b".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_dot() {
-        let nfa = compile("^a.c$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"abcd".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_dot_quantifier_plus() {
-        let nfa = compile("^a.+c$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
-        assert_eq!(
-            match_likelihood(&nfa, &"abbc".to_string(), false),
-            Some(1.0)
-        );
-        assert_eq!(match_likelihood(&nfa, &"abcd".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_dot_quantifier_question() {
-        let nfa = compile("^a.?c$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"ac".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"abbc".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"abcd".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_dot_quantifier_exact() {
-        let nfa = compile("^a.{2}c$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"ac".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"ab".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), None);
-        assert_eq!(
-            match_likelihood(&nfa, &"abbc".to_string(), false),
-            Some(1.0)
-        );
-        assert_eq!(match_likelihood(&nfa, &"abbbc".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"abcd".to_string(), false), None);
-    }
-
-    #[test]
-    fn test_short_class() {
-        let nfa = compile("^a\\dc$").unwrap();
-
-        assert_eq!(match_likelihood(&nfa, &"ac".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"a1c".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"a2c".to_string(), false), Some(1.0));
-        assert_eq!(match_likelihood(&nfa, &"a12c".to_string(), false), None);
-        assert_eq!(match_likelihood(&nfa, &"abc".to_string(), false), None);
-    }
-}
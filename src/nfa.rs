@@ -1,7 +1,5 @@
 use crate::ast::{AstNode, Kind};
-use crate::distribution::{Dist, DistLink};
-use crate::parser::parse;
-use itertools::Itertools;
+use crate::distribution::DistLink;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct State {
@@ -127,27 +125,25 @@ pub fn ast_to_nfa(ast: AstNode, index: usize, out: usize) -> Vec<State> {
 
 fn ast_to_frag(ast: AstNode, index: usize, outs: Outs, distribution: Option<DistLink>) -> Frag {
     match ast.kind {
-        Kind::Alternation(left, right) => {
+        Kind::Alternation(left, right, dist) => {
             /*
                       ┌──► left ───┐
                 ──► split         outs ──►
                       └──► right ──┘
             */
-            let right = ast_to_frag(*right, index + left.length + 1, outs, None);
+            // A chain of alternations shares the distribution over its branches,
+            // the split weighs the left branch and passes the rest down the chain
+            let dist = dist.or(distribution);
+            let right_dist = match right.kind {
+                Kind::Alternation(_, _, None) => dist.as_ref().and_then(DistLink::rest_branches),
+                _ => None,
+            };
+            let right = ast_to_frag(*right, index + left.length + 1, outs, right_dist);
             let left = ast_to_frag(*left, index + 1, outs, None);
-            let split = ast_to_frag(
-                AstNode {
-                    length: 1,
-                    kind: Kind::Split,
-                    // TODO use distribution
-                },
-                index,
-                (Some(left.start), Some(right.start)),
-                None,
-            );
+            let split = State::new(Kind::Split, (Some(left.start), Some(right.start)), dist);
             Frag {
-                states: [split.states, left.states, right.states].concat(),
-                start: split.start,
+                states: [vec![split], left.states, right.states].concat(),
+                start: index,
                 outs,
             }
         }
@@ -173,6 +169,31 @@ fn ast_to_frag(ast: AstNode, index: usize, outs: Outs, distribution: Option<Dist
                 outs: right.outs,
             }
         }
+        Kind::Group(group, name, inner) => {
+            /*
+                ──► open ──► inner ──► close ──► outs
+            */
+            let close_index = index + 1 + inner.length;
+            let inner = ast_to_frag(*inner, index + 1, (Some(close_index), None), None);
+            let open = State::new(
+                Kind::GroupOpen(group, name),
+                (Some(inner.start), None),
+                None,
+            );
+            let close = State::new(Kind::GroupClose(group), outs, None);
+            Frag {
+                states: [vec![open], inner.states, vec![close]].concat(),
+                start: index,
+                outs,
+            }
+        }
+        Kind::GroupOpen(_, _) | Kind::GroupClose(_) => Frag {
+            // boundary points to outs
+            // boundary as start
+            states: vec![State::from(ast, outs)],
+            start: index,
+            outs,
+        },
         Kind::Literal(_) | Kind::Dot => Frag {
             // literal points to outs
             // literal as start
@@ -187,7 +208,20 @@ fn ast_to_frag(ast: AstNode, index: usize, outs: Outs, distribution: Option<Dist
             start: index,
             outs,
         },
-        Kind::Class(_, _) => Frag {
+        // without a distribution over them, members are only looked up, by binary search
+        Kind::Class(is_negate, mut chars) if distribution.is_none() => Frag {
+            states: vec![State::new(
+                Kind::Class(is_negate, {
+                    chars.sort_unstable();
+                    chars
+                }),
+                outs,
+                None,
+            )],
+            start: index,
+            outs,
+        },
+        Kind::Class(_, _) | Kind::FoldedClass(_, _) => Frag {
             // class points to outs
             // class as start
             states: vec![State::new(ast.kind, outs, distribution)],
@@ -197,7 +231,7 @@ fn ast_to_frag(ast: AstNode, index: usize, outs: Outs, distribution: Option<Dist
         Kind::Quantified(quantifier, quantified, distribution) => {
             quantifier_to_frag(*quantifier, *quantified, index, outs, distribution)
         }
-        Kind::Quantifier(_) | Kind::ExactQuantifier(_) => Frag {
+        Kind::Quantifier(_) | Kind::ExactQuantifier(_) | Kind::RangeQuantifier(_, _) => Frag {
             // quantifier points to outs
             // quantifier as start
             states: vec![State::new(ast.kind, outs, distribution)],
@@ -225,6 +259,12 @@ fn ast_to_frag(ast: AstNode, index: usize, outs: Outs, distribution: Option<Dist
             start: index,
             outs,
         },
+        Kind::Flags(_, _)
+        | Kind::FlagGroup(_, _, _)
+        | Kind::DotClassified(_, _)
+        | Kind::ExcludingClass(_, _) => {
+            unreachable!("flags are applied when parsing, see ast::apply_flags")
+        }
     }
 }
 
@@ -284,7 +324,7 @@ fn quantifier_to_frag(
                 }
             }
         }
-        Kind::ExactQuantifier(_) => {
+        Kind::ExactQuantifier(_) | Kind::RangeQuantifier(_, _) => {
             /*
                         ┌───────◄───────┐
                 ──► quantifier ──► quantified
@@ -315,6 +355,8 @@ fn quantifier_to_frag(
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::distribution::Dist;
+    use crate::parser::parse;
 
     #[test]
     fn test_compile_simple() {
@@ -368,6 +410,7 @@ mod test {
                         length: 1,
                         kind: Kind::Literal('b'),
                     }),
+                    None,
                 ),
             },
             0,
@@ -1006,4 +1049,20 @@ mod test {
         ];
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_asts_to_nfa_group() {
+        let asts = parse("(a)+b").unwrap();
+        let result = asts_to_nfa(asts);
+        let expected = vec![
+            State::start(Some(1)),
+            State::new(Kind::Quantifier('+'), (Some(2), Some(5)), None),
+            State::new(Kind::GroupOpen(1, None), (Some(3), None), None),
+            State::literal('a', (Some(4), None)),
+            State::new(Kind::GroupClose(1), (Some(1), None), None),
+            State::literal('b', (Some(6), None)),
+            State::terminal(),
+        ];
+        assert_eq!(result, expected);
+    }
 }
@@ -1,29 +1,162 @@
-use crate::ast::{build_ast_from_expr, AstNode, Kind};
-use pest::{iterators::Pair, Parser};
+use crate::ast::{apply_flags, build_ast_from_expr, number_groups, AstNode, Kind};
+use crate::error::{CompileError, Span};
+use pest::Parser;
 use pest_derive::Parser;
 
 #[derive(Parser)]
 #[grammar = "grammar.pest"]
 struct RegexParser;
 
-pub fn parse(source: &str) -> std::result::Result<Vec<AstNode>, pest::error::Error<Rule>> {
-    let mut ast = Vec::new();
-    let pairs = RegexParser::parse(Rule::Regex, source)?;
+pub fn parse(source: &str) -> Result<Vec<AstNode>, CompileError> {
+    let verbose = Verbose::strip(source);
+    let parse_stripped = || {
+        let mut ast = Vec::new();
+        let pairs = RegexParser::parse(Rule::Regex, &verbose.stripped)?;
 
-    for pair in pairs {
-        if let Rule::EOI = pair.as_rule() {
-            ast.push(AstNode {
-                length: 0,
-                kind: Kind::Terminal,
-            });
-        } else {
-            let node = build_ast_from_expr(pair);
-            ast.push(node);
+        for pair in pairs {
+            if let Rule::EOI = pair.as_rule() {
+                ast.push(AstNode {
+                    length: 0,
+                    kind: Kind::Terminal,
+                });
+            } else {
+                let node = build_ast_from_expr(pair)?;
+                ast.push(node);
+            }
         }
-    }
+        Ok(ast)
+    };
+    let mut ast = apply_flags(parse_stripped().map_err(|e| verbose.locate_error(e))?);
+    number_groups(&mut ast);
     Ok(ast)
 }
 
+/// Spans of the distributions of quantifiers, classes and alternatives in source, in order of appearance
+pub fn dist_spans(source: &str) -> Result<Vec<Span>, CompileError> {
+    let verbose = Verbose::strip(source);
+    Ok(RegexParser::parse(Rule::Regex, &verbose.stripped)
+        .map_err(|e| verbose.locate_error(e.into()))?
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::Dist)
+        .map(|pair| verbose.locate(pair.as_span().into()))
+        .collect())
+}
+
+/// Source with whitespace and `#` comments left out where `(?x)` is on
+///
+/// Verbose mode lasts up to the end of the group it is turned on in, like
+/// other flags. Whitespace that is escaped or among the members of a class is
+/// kept.
+struct Verbose {
+    stripped: String,
+    /// Offset in source of every byte that is kept
+    offsets: Vec<usize>,
+    source_len: usize,
+}
+
+impl Verbose {
+    fn strip(source: &str) -> Verbose {
+        let mut stripped = String::new();
+        let mut offsets = Vec::new();
+        let mut keep = |i: usize, c: char| {
+            stripped.push(c);
+            offsets.extend(i..i + c.len_utf8());
+        };
+        let mut is_verbose = false;
+        // Verbose mode to restore as each open group closes
+        let mut scopes: Vec<bool> = Vec::new();
+        let mut is_class = false;
+
+        let mut chars = source.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    keep(i, c);
+                    if let Some((j, escaped)) = chars.next() {
+                        keep(j, escaped);
+                    }
+                }
+                // [:alpha:] closes with a bracket of its own
+                '[' if is_class && source[i..].starts_with("[:") => {
+                    keep(i, c);
+                    while let Some((j, c)) = chars.next() {
+                        keep(j, c);
+                        if c == ':' && chars.peek().map(|&(_, c)| c) == Some(']') {
+                            let (j, c) = chars.next().unwrap();
+                            keep(j, c);
+                            break;
+                        }
+                    }
+                }
+                '[' => {
+                    is_class = true;
+                    keep(i, c);
+                }
+                // The distribution of a class reads like the rest of the pattern
+                '~' | ']' => {
+                    is_class = false;
+                    keep(i, c);
+                }
+                _ if is_class => keep(i, c),
+                '(' => {
+                    keep(i, c);
+                    scopes.push(is_verbose);
+                    let flags: String = source[i + 1..]
+                        .chars()
+                        .take_while(|c| matches!(c, '?' | 'i' | 's' | 'x' | '-'))
+                        .collect();
+                    let is_flag_group = flags.starts_with('?') && flags.len() > 1;
+                    let closing = source[i + 1 + flags.len()..].chars().next();
+                    if let (true, Some(closing @ (')' | ':'))) = (is_flag_group, closing) {
+                        // Flags with no group of their own apply to the enclosing one
+                        if closing == ')' {
+                            scopes.pop();
+                        }
+                        let (on, off) = flags[1..].split_once('-').unwrap_or((&flags[1..], ""));
+                        if on.contains('x') {
+                            is_verbose = true;
+                        } else if off.contains('x') {
+                            is_verbose = false;
+                        }
+                        for _ in 0..flags.len() + 1 {
+                            let (j, c) = chars.next().unwrap();
+                            keep(j, c);
+                        }
+                    }
+                }
+                ')' => {
+                    is_verbose = scopes.pop().unwrap_or(is_verbose);
+                    keep(i, c);
+                }
+                '#' if is_verbose => while chars.next_if(|&(_, c)| c != '\n').is_some() {},
+                _ if is_verbose && c.is_whitespace() => {}
+                _ => keep(i, c),
+            }
+        }
+        Verbose {
+            stripped,
+            offsets,
+            source_len: source.len(),
+        }
+    }
+
+    /// Span in source of a span in the stripped source
+    fn locate(&self, span: Span) -> Span {
+        let start = self.offsets.get(span.start).copied();
+        let start = start.unwrap_or(self.source_len);
+        let end = match span.end > span.start {
+            true => self.offsets[span.end - 1] + 1,
+            false => start,
+        };
+        Span::new(start, end)
+    }
+
+    fn locate_error(&self, mut error: CompileError) -> CompileError {
+        *error.span_mut() = self.locate(*error.span_mut());
+        error
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -67,6 +200,7 @@ mod test {
                         length: 1,
                         kind: Kind::Literal('b'),
                     }),
+                    None,
                 ),
             },
             AstNode {
@@ -217,6 +351,58 @@ mod test {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_parser_range_quantifier_dist_ast() {
+        let result = parse("a{2,6~Geo(0.3)}").unwrap_or_default();
+        let expected = vec![
+            AstNode {
+                length: 2,
+                kind: Kind::Quantified(
+                    Box::new(AstNode {
+                        length: 1,
+                        kind: Kind::RangeQuantifier(2, 6),
+                    }),
+                    Box::new(AstNode {
+                        length: 1,
+                        kind: Kind::Literal('a'),
+                    }),
+                    Some(Dist::PGeometric(2, 6, 0.3).count()),
+                ),
+            },
+            AstNode {
+                length: 0,
+                kind: Kind::Terminal,
+            },
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parser_range_unbounded_quantifier_ast() {
+        let result = parse("a{2,}").unwrap_or_default();
+        let expected = vec![
+            AstNode {
+                length: 2,
+                kind: Kind::Quantified(
+                    Box::new(AstNode {
+                        length: 1,
+                        kind: Kind::RangeQuantifier(2, u64::MAX),
+                    }),
+                    Box::new(AstNode {
+                        length: 1,
+                        kind: Kind::Literal('a'),
+                    }),
+                    Some(Dist::Constant(2, u64::MAX, 1.0).count()),
+                ),
+            },
+            AstNode {
+                length: 0,
+                kind: Kind::Terminal,
+            },
+        ];
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn test_parser_exact_class_ast() {
         let result = parse("[abc]").unwrap_or_default();
@@ -233,6 +419,182 @@ mod test {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_parser_class_range_ast() {
+        let result = parse("[a-cx]").unwrap_or_default();
+        let expected = vec![
+            AstNode {
+                length: 1,
+                kind: Kind::Class(false, vec!['a', 'b', 'c', 'x']),
+            },
+            AstNode {
+                length: 0,
+                kind: Kind::Terminal,
+            },
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parser_class_range_members() {
+        let chars = |source: &str| match parse(source).unwrap().remove(0).kind {
+            Kind::Class(_, chars) => chars,
+            _ => unreachable!(),
+        };
+        assert_eq!(chars("[0-2_]"), vec!['0', '1', '2', '_']);
+        assert_eq!(chars("[-a]"), vec!['-', 'a']);
+        assert_eq!(chars("[a-]"), vec!['a', '-']);
+        assert_eq!(chars("[a\\-c]"), vec!['a', '-', 'c']);
+        assert_eq!(chars("[\\--/]"), vec!['-', '.', '/']);
+        assert_eq!(
+            chars("[\\d-]"),
+            vec!['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '-']
+        );
+        // repeated members keep their first position
+        assert_eq!(chars("[ca-c]"), vec!['c', 'a', 'b']);
+    }
+
+    #[test]
+    fn test_parser_unicode_literals() {
+        assert_eq!(
+            parse("é日_/:@").unwrap()[0].kind,
+            parse("\\é\\日\\_\\/\\:\\@").unwrap()[0].kind
+        );
+        for source in ["a]", "a}", "a~", "(a"] {
+            assert!(
+                matches!(parse(source), Err(CompileError::Syntax(_, _))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_named_classes() {
+        let class = |source: &str| match parse(source).unwrap().remove(0).kind {
+            Kind::Class(is_negate, chars) => (is_negate, chars.into_iter().collect::<String>()),
+            kind => panic!("{} is not a class", kind),
+        };
+        let word = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz";
+        assert_eq!(class("\\w"), (false, word.to_string()));
+        assert_eq!(class("[:word:]"), (false, word.to_string()));
+        assert_eq!(class("\\W"), (true, word.to_string()));
+        assert_eq!(class("\\D"), (true, "0123456789".to_string()));
+        assert_eq!(class("\\S"), (true, " \t\n\r\x0c\x0b".to_string()));
+        assert_eq!(class("[:xdigit:]").1, "0123456789ABCDEFabcdef");
+        assert_eq!(class("[:punct:]").1, "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~");
+        assert_eq!(class("[:blank:]").1, "\t ");
+        assert_eq!(class("[:cntrl:]").1.len(), 33);
+        assert_eq!(class("[:print:]").1.len(), 95);
+        assert_eq!(class("[:^upper:]"), (true, ('A'..='Z').collect()));
+
+        // within a class a negated part keeps to the characters it excludes,
+        // less the other members
+        assert_eq!(class("[\\Da5]"), (true, "012346789".to_string()));
+        assert_eq!(class("[^\\D\\W]"), (false, "0123456789".to_string()));
+        assert_eq!(class("[[:lower:][:digit:]]").1.len(), 36);
+
+        // unless a distribution over the members needs them listed, in code point order
+        let chars = match parse("[\\Da~Cat(a=0.5)]").unwrap().remove(0).kind {
+            Kind::Classified(class, _) => match class.kind {
+                Kind::Class(false, chars) => chars.into_iter().collect::<String>(),
+                kind => panic!("{} is not a listed class", kind),
+            },
+            kind => panic!("{} is not a classified class", kind),
+        };
+        assert!(chars.starts_with("\0\x01") && !chars.contains('5'));
+        assert!(chars.find('/') < chars.find('a'));
+    }
+
+    #[test]
+    fn test_parser_escapes() {
+        let literal = |source: &str| match parse(source).unwrap().remove(0).kind {
+            Kind::Literal(c) => c,
+            kind => panic!("{} is not a literal", kind),
+        };
+        assert_eq!(literal("\\t"), '\t');
+        assert_eq!(literal("\\n"), '\n');
+        assert_eq!(literal("\\r"), '\r');
+        assert_eq!(literal("\\0"), '\0');
+        assert_eq!(literal("\\x41"), 'A');
+        assert_eq!(literal("\\u{e9}"), 'é');
+        assert_eq!(literal("\\u{1F600}"), '😀');
+        assert_eq!(literal("\\."), '.');
+
+        match parse("[\\x30-\\x32\\t]").unwrap().remove(0).kind {
+            Kind::Class(_, chars) => assert_eq!(chars, vec!['0', '1', '2', '\t']),
+            kind => panic!("{} is not a class", kind),
+        }
+
+        assert_eq!(
+            parse("a\\u{D800}"),
+            Err(CompileError::InvalidEscape(
+                Span::new(1, 9),
+                "D800 is not a Unicode scalar value".to_string()
+            ))
+        );
+        assert!(matches!(
+            parse("[\\u{110000}]"),
+            Err(CompileError::InvalidEscape(_, _))
+        ));
+        for source in ["\\x4", "\\u{}", "\\u{1234567}", "\\u41"] {
+            assert!(
+                matches!(parse(source), Err(CompileError::Syntax(_, _))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_property_class() {
+        let class = |source: &str| match parse(source).unwrap().remove(0).kind {
+            Kind::Class(is_negate, chars) => (is_negate, chars),
+            kind => panic!("{} is not a class", kind),
+        };
+        // members are listed in code point order
+        let (is_negate, digits) = class("\\p{Nd}");
+        assert!(!is_negate);
+        assert_eq!(
+            digits[..10],
+            ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9']
+        );
+        assert!(digits.contains(&'٣') && !digits.contains(&'Ⅻ'));
+        assert!(digits.windows(2).all(|w| w[0] < w[1]));
+
+        let (is_negate, letters) = class("\\P{L}");
+        assert!(is_negate);
+        assert!(['a', 'Z', 'é', '日'].iter().all(|c| letters.contains(c)));
+        assert_eq!(
+            letters.len(),
+            ["Lu", "Ll", "Lt", "Lm", "Lo"]
+                .iter()
+                .map(|name| class(&format!("\\p{{{}}}", name)).1.len())
+                .sum()
+        );
+
+        // within a class, \P excludes the letters, less the other members
+        let (is_negate, chars) = class("[x\\P{L}]");
+        assert!(is_negate);
+        assert!(!chars.contains(&'x') && !chars.contains(&'1') && chars.contains(&'y'));
+
+        assert!(matches!(
+            parse("\\p{Nd}~Zipf(1.0)").unwrap()[0].kind,
+            Kind::Classified(_, Some(DistLink::Indexed(Dist::PZipf(0, _, _))))
+        ));
+        assert_eq!(
+            parse("\\p{Xx}"),
+            Err(CompileError::UnknownClass(
+                Span::new(3, 5),
+                "Xx".to_string()
+            ))
+        );
+        assert!(matches!(
+            parse("\\p{Lux}"),
+            Err(CompileError::UnknownClass(_, _))
+        ));
+    }
+
     #[test]
     fn test_parser_exact_class_indexed_dist_ast() {
         let result = parse("[abc~Geo(0.5)]").unwrap_or_default();
@@ -708,12 +1070,39 @@ mod test {
 
     #[test]
     fn test_parser_parentheses() {
-        assert_eq!(ast_as_str(parse("(a)").unwrap()), "a");
-        assert_eq!(ast_as_str(parse("(ab)c").unwrap()), "ab.c.");
-        assert_eq!(ast_as_str(parse("a(bc)").unwrap()), "abc..");
-        assert_eq!(ast_as_str(parse("(a(bc))d").unwrap()), "abc..d.");
-        assert_eq!(ast_as_str(parse("(a|b)").unwrap()), "a|b");
-        assert_eq!(ast_as_str(parse("(a|b)c").unwrap()), "a|bc."); // TODO not a great representation
+        assert_eq!(ast_as_str(parse("(?:a)").unwrap()), "a");
+        assert_eq!(ast_as_str(parse("(?:ab)c").unwrap()), "ab.c.");
+        assert_eq!(ast_as_str(parse("a(?:bc)").unwrap()), "abc..");
+        assert_eq!(ast_as_str(parse("(?:a(?:bc))d").unwrap()), "abc..d.");
+        assert_eq!(ast_as_str(parse("(?:a|b)").unwrap()), "a|b");
+        assert_eq!(ast_as_str(parse("(?:a|b)c").unwrap()), "a|bc."); // TODO not a great representation
+    }
+
+    #[test]
+    fn test_parser_groups() {
+        assert_eq!(ast_as_str(parse("(a)").unwrap()), "(a)");
+        assert_eq!(ast_as_str(parse("(a(bc))d").unwrap()), "(a(bc.).)d.");
+        assert_eq!(ast_as_str(parse("(?<x_1>a|b)+").unwrap()), "(?<x_1>a|b)+");
+
+        // numbered in the order they open
+        let nfa = crate::nfa::asts_to_nfa(parse("((a)(?:b)(?<c>c))").unwrap());
+        let opens: Vec<Kind> = nfa
+            .into_iter()
+            .map(|state| state.kind)
+            .filter(|kind| matches!(kind, Kind::GroupOpen(_, _)))
+            .collect();
+        assert_eq!(
+            opens,
+            vec![
+                Kind::GroupOpen(1, None),
+                Kind::GroupOpen(2, None),
+                Kind::GroupOpen(3, Some("c".to_string()))
+            ]
+        );
+
+        assert!(parse("(?<1x>a)").is_err());
+        assert!(parse("(?a)").is_err());
+        assert!(parse("()").is_err());
     }
 
     #[test]
@@ -722,11 +1111,342 @@ mod test {
         assert_eq!(ast_as_str(parse("a{20}").unwrap()), "a{20}");
     }
 
+    #[test]
+    fn test_parser_range_quantifier() {
+        assert_eq!(ast_as_str(parse("a{2,6}").unwrap()), "a{2,6}");
+        assert_eq!(ast_as_str(parse("a{2,}").unwrap()), "a{2,}");
+        assert_eq!(
+            ast_as_str(parse("a{2,6~Geo(0.5)}").unwrap()),
+            "a{2,6~Geo(0.5)}"
+        );
+    }
+
     #[test]
     fn test_parser_exact_quantifier_dist() {
         assert_eq!(ast_as_str(parse("a{2~Geo(1.0)}").unwrap()), "a{2~Geo(1)}");
     }
 
+    #[test]
+    fn test_parser_count_dists() {
+        assert_eq!(
+            ast_as_str(parse("a{2~Pois(3.5)}").unwrap()),
+            "a{2~Pois(3.5)}"
+        );
+        assert_eq!(
+            ast_as_str(parse("a{2,9~NegBin(2,0.25)}").unwrap()),
+            "a{2,9~NegBin(2,0.25)}"
+        );
+        match &parse("a{2~NegBin}").unwrap()[0].kind {
+            Kind::Quantified(_, _, dist) => {
+                assert_eq!(
+                    *dist,
+                    Some(Dist::PNegBinomial(2, u64::MAX, 1.0, 0.5).count())
+                )
+            }
+            kind => panic!("{} is not quantified", kind),
+        }
+
+        assert_eq!(
+            ast_as_str(parse("a{8~Norm(1.5)}").unwrap()),
+            "a{8~Norm(1.5)}"
+        );
+        match &parse("a{2,5~Laplace}").unwrap()[0].kind {
+            Kind::Quantified(_, _, dist) => {
+                assert_eq!(*dist, Some(Dist::PLaplace(2, 5, 3.5, 1.0).count()))
+            }
+            kind => panic!("{} is not quantified", kind),
+        }
+
+        assert_eq!(
+            ast_as_str(parse("a{1~Zeta(2.5)}").unwrap()),
+            "a{1~Zeta(2.5)}"
+        );
+        match &parse("a{3,~Zeta}").unwrap()[0].kind {
+            Kind::Quantified(_, _, dist) => {
+                assert_eq!(*dist, Some(Dist::PZeta(3, u64::MAX, 2.0).count()))
+            }
+            kind => panic!("{} is not quantified", kind),
+        }
+        assert!(matches!(
+            parse("[ab~Zeta(2.0)]"),
+            Err(CompileError::InvalidDistribution(_, _))
+        ));
+        // no mass for zero repetitions
+        assert_eq!(
+            parse("xa*~Zeta(2.0)"),
+            Err(CompileError::InvalidDistribution(
+                Span::new(3, 13),
+                "Zeta needs at least one repetition, i.e. a+~Zeta or a{1,5~Zeta}".to_string()
+            ))
+        );
+        for source in ["a?~Zeta", "a{0,3~Zeta}"] {
+            assert!(matches!(
+                parse(source),
+                Err(CompileError::InvalidDistribution(_, _))
+            ));
+        }
+        assert!(parse("a+~Zeta(2.0)").is_ok());
+
+        for source in [
+            "a{2~Pois(0.0)}",
+            "a{2~Zeta(1.0)}",
+            "a{2~NegBin(0,0.5)}",
+            "a{2~NegBin(2,1.5)}",
+            "a{2~Norm(0.0)}",
+            "a{2~Laplace(0)}",
+        ] {
+            assert!(
+                matches!(parse(source), Err(CompileError::InvalidParameter(_, _))),
+                "{}",
+                source
+            );
+        }
+        assert!(matches!(
+            parse("a{2~NegBin(2,0.5,1)}"),
+            Err(CompileError::InvalidDistribution(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_parser_mixture() {
+        let source = "a{2~Mix(0.3*Geo(0.5),0.7*Norm(2))}";
+        assert_eq!(ast_as_str(parse(source).unwrap()), source);
+        match &parse(source).unwrap()[0].kind {
+            Kind::Quantified(_, _, dist) => assert_eq!(
+                *dist,
+                Some(
+                    Dist::Mixture(vec![
+                        (0.3, Dist::PGeometric(2, u64::MAX, 0.5)),
+                        (0.7, Dist::PNormal(0, u64::MAX, 2.0, 2.0)),
+                    ])
+                    .count()
+                )
+            ),
+            kind => panic!("{} is not quantified", kind),
+        }
+        assert_eq!(
+            ast_as_str(parse("[ab~Mix(0.5*Cat(a=0.9),0.5*Zipf)]").unwrap()),
+            "[[ab]~Mix(0.5*Cat,0.5*Zipf(1))]"
+        );
+
+        assert_eq!(
+            parse("a{2~Mix(0.3*Geo,0.6*Pois)}"),
+            Err(CompileError::InvalidParameter(
+                Span::new(3, 25),
+                "weights must sum to 1".to_string()
+            ))
+        );
+        for source in [
+            "a{2~Mix(1.5*Geo,0.5*Pois)}",
+            "a{2~Mix(0.5*Geo(1.5),0.5*Pois)}",
+        ] {
+            assert!(
+                matches!(parse(source), Err(CompileError::InvalidParameter(_, _))),
+                "{}",
+                source
+            );
+        }
+        for source in [
+            "a{2~Mix(0.5)}",
+            "a{2~Mix}",
+            "a{2~Geo(1.0*Pois)}",
+            "a{2~Mix(0.5*Const,0.5*Geo)}",
+            "(a|b)~Mix(1.0*Cat)",
+        ] {
+            assert!(
+                matches!(parse(source), Err(CompileError::InvalidDistribution(_, _))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_histogram() {
+        let histogram = |asts: Vec<AstNode>| match &asts[0].kind {
+            Kind::Quantified(_, _, Some(DistLink::Counted(dist))) => dist.clone(),
+            kind => panic!("{} has no count distribution", kind),
+        };
+
+        // weights are normalized, the count may be left out
+        assert_eq!(
+            histogram(parse("a{~Hist(3=1,4=6,10=3)}").unwrap()),
+            Dist::Histogram([(3, 0.1), (4, 0.6), (10, 0.3)].into())
+        );
+        assert_eq!(
+            ast_as_str(parse("a{~Hist(3=0.25,4=0.75)}").unwrap()),
+            "a{0~Hist(3=0.25,4=0.75)}"
+        );
+
+        let path = std::env::temp_dir().join("pregex_test_parser_histogram.tsv");
+        std::fs::write(&path, "3\t1\n\n10\t3\n").unwrap();
+        let source = format!("a{{2,10~Hist(@{})}}", path.display());
+        assert_eq!(
+            histogram(parse(&source).unwrap()),
+            Dist::Histogram([(3, 0.25), (10, 0.75)].into())
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            parse(&source),
+            Err(CompileError::InvalidParameter(_, _))
+        ));
+
+        // only counts within the bounds of the quantifier are kept
+        assert_eq!(
+            histogram(parse("a{3~Hist(2=0.5,4=0.5)}").unwrap()),
+            Dist::Histogram([(4, 1.0)].into())
+        );
+        assert_eq!(
+            parse("a{5,6~Hist(2=0.5,4=0.5)}"),
+            Err(CompileError::InvalidParameter(
+                Span::new(5, 23),
+                "Hist needs a count from 5 to 6 with positive weight".to_string()
+            ))
+        );
+
+        for source in ["a{~Hist(x=0.5)}", "a{~Hist(3=0)}", "[0-9~Cat(10=0.5)]"] {
+            assert!(
+                matches!(parse(source), Err(CompileError::InvalidParameter(_, _))),
+                "{}",
+                source
+            );
+        }
+        for source in ["a{~Geo}", "a{~Geo(0.5)}"] {
+            assert!(
+                matches!(parse(source), Err(CompileError::InvalidQuantifier(_, _))),
+                "{}",
+                source
+            );
+        }
+        for source in [
+            "[ab~Hist(1=1.0)]",
+            "a{2~Geo(@lengths.tsv)}",
+            "a{2~Hist(0.5)}",
+        ] {
+            assert!(
+                matches!(parse(source), Err(CompileError::InvalidDistribution(_, _))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_dirichlet() {
+        let prob_mass = |source: &str| match &parse(source).unwrap()[0].kind {
+            Kind::Classified(_, Some(DistLink::Indexed(Dist::Categorical(prob_mass)))) => {
+                prob_mass.clone()
+            }
+            kind => panic!("{} is not a categorical class", kind),
+        };
+
+        // counts plus one for each member, d is unseen but keeps mass
+        assert_eq!(
+            prob_mass("[abcd~Dir(a=30,b=5,c=1)]"),
+            vec![0.0, 31.0 / 40.0, 6.0 / 40.0, 2.0 / 40.0, 1.0 / 40.0]
+        );
+        assert_eq!(
+            prob_mass("[ab~Dir(0.5,a=3)]"),
+            vec![0.0, 3.5 / 4.0, 0.5 / 4.0]
+        );
+        // the remainder of a negated class gets the prior too
+        assert_eq!(
+            prob_mass("[^ab~Dir(a=1,.=6)]"),
+            vec![7.0 / 10.0, 2.0 / 10.0, 1.0 / 10.0]
+        );
+
+        for source in ["[ab~Dir(c=1)]", "[ab~Dir(0.0,a=1)]"] {
+            assert!(
+                matches!(parse(source), Err(CompileError::InvalidParameter(_, _))),
+                "{}",
+                source
+            );
+        }
+        for source in ["a{2~Dir(a=1)}", "(a|b)~Dir", "[ab~Dir(1.0,2.0)]"] {
+            assert!(
+                matches!(parse(source), Err(CompileError::InvalidDistribution(_, _))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parser_short_quantifier_dists() {
+        for source in ["a?~Ber(0.8)", "a*~Geo(0.4)", "(x)+~Pois(2)"] {
+            assert_eq!(ast_as_str(parse(source).unwrap()), source);
+        }
+        match &parse("a?~Ber(0.8)").unwrap()[0].kind {
+            Kind::Quantified(_, _, dist) => {
+                assert_eq!(*dist, Some(Dist::PBernoulli(0, 1, 0.8).count()))
+            }
+            kind => panic!("{} is not quantified", kind),
+        }
+        match &parse("a+~Geo").unwrap()[0].kind {
+            Kind::Quantified(_, _, dist) => {
+                assert_eq!(*dist, Some(Dist::PGeometric(1, u64::MAX, 0.5).count()))
+            }
+            kind => panic!("{} is not quantified", kind),
+        }
+
+        // * and + are unbounded, so need a distribution with a tail
+        for source in ["a*~Bin(0.5)", "a+~Zipf"] {
+            assert!(parse(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_parser_bare_class_dists() {
+        let digits: Vec<char> = ('0'..='9').collect();
+        for source in ["\\d~Zipf(1.2)", "[:digit:]~Zipf(1.2)", "[\\d~Zipf(1.2)]"] {
+            assert_eq!(
+                parse(source).unwrap()[0].kind,
+                Kind::Classified(
+                    Box::new(AstNode {
+                        length: 1,
+                        kind: Kind::Class(false, digits.clone()),
+                    }),
+                    Some(Dist::PZipf(0, 10, 1.2).index()),
+                ),
+                "{}",
+                source
+            );
+        }
+
+        // the alphabet of . is named by the distribution, other characters share the rest,
+        // but a newline only with (?s)
+        for (source, chars, mass) in [
+            (
+                ".~Cat(b=0.3,a=0.5)",
+                vec!['b', 'a', '\n'],
+                vec![0.3, 0.5, 0.0],
+            ),
+            ("(?s).~Cat(b=0.3,a=0.5)", vec!['b', 'a'], vec![0.3, 0.5]),
+        ] {
+            match &parse(source).unwrap()[0].kind {
+                Kind::Classified(class, Some(DistLink::Indexed(Dist::Categorical(prob_mass)))) => {
+                    assert_eq!(class.kind, Kind::Class(true, chars), "{}", source);
+                    assert_eq!(prob_mass[1..], mass, "{}", source);
+                    assert!((prob_mass[0] - 0.2).abs() < 1e-12, "{}", source);
+                }
+                kind => panic!("{} is not a categorical class", kind),
+            }
+        }
+        assert_eq!(parse(".").unwrap()[0].kind, Kind::Dot);
+        assert_eq!(
+            ast_as_str(parse("\\d~Geo(0.5){2}").unwrap()),
+            "[[0123..]~Geo(0.5)]{2}"
+        );
+
+        for source in [".~Geo", ".~Cat(.=1)", ".~Zipf(a=1)"] {
+            assert!(
+                matches!(parse(source), Err(CompileError::InvalidDistribution(_, _))),
+                "{}",
+                source
+            );
+        }
+    }
+
     #[test]
     fn test_parser_exact_class() {
         assert_eq!(ast_as_str(parse("[ab]").unwrap()), "[ab]");
@@ -738,4 +1458,214 @@ mod test {
         assert_eq!(ast_as_str(parse("[ab~Const]").unwrap()), "[[ab]]");
         assert_eq!(ast_as_str(parse("[ab~Geo(1.0)]").unwrap()), "[[ab]~Geo(1)]");
     }
+
+    #[test]
+    fn test_parser_flags() {
+        assert_eq!(ast_as_str(parse("(?i)a1").unwrap()), "[aA]1.");
+        assert_eq!(ast_as_str(parse("(?s).").unwrap()), "[^]");
+        assert_eq!(ast_as_str(parse(".").unwrap()), ".");
+        // flags last to the end of the enclosing group
+        assert_eq!(ast_as_str(parse("(a(?i)b)c").unwrap()), "(a[bB].)c.");
+        assert_eq!(ast_as_str(parse("(?i:a)b").unwrap()), "[aA]b.");
+        assert_eq!(ast_as_str(parse("(?i)a(?-i)b").unwrap()), "[aA]b.");
+        assert_eq!(
+            ast_as_str(parse("(?i)[ab~Cat(a=0.7,b=0.3)]").unwrap()),
+            "[[ab]~Cat]"
+        );
+        assert!(matches!(parse("a(?i)"), Err(CompileError::Syntax(_, _))));
+    }
+
+    #[test]
+    fn test_parser_verbose() {
+        assert_eq!(ast_as_str(parse("(?x) a b # c\n c").unwrap()), "abc..");
+        // whitespace is kept when escaped or within a class
+        assert_eq!(ast_as_str(parse("(?x)[ ]\\ b").unwrap()), "[ ] b..");
+        assert_eq!(ast_as_str(parse("(?x:a b) c").unwrap()), "ab. .c.");
+        assert_eq!(
+            ast_as_str(parse("(?x)[a b~ Cat( a=0.7, b=0.3 )]").unwrap()),
+            "[[a b]~Cat]"
+        );
+        // spans point into the source as written
+        assert_eq!(
+            parse("(?x) a  b{2~Foo}"),
+            Err(CompileError::UnknownDistribution(
+                Span::new(12, 15),
+                "Foo".to_string()
+            ))
+        );
+        let source = "(?x) a{1 ~Geo(0.5)}  # count\n [ab~Cat]";
+        let spans = dist_spans(source).unwrap();
+        let dists: Vec<&str> = spans.iter().map(|s| &source[s.start..s.end]).collect();
+        assert_eq!(dists, vec!["~Geo(0.5)", "~Cat"]);
+    }
+
+    #[test]
+    fn test_parser_error_syntax() {
+        assert!(matches!(parse("a{2"), Err(CompileError::Syntax(_, _))));
+        assert!(matches!(parse("[ab"), Err(CompileError::Syntax(_, _))));
+    }
+
+    #[test]
+    fn test_parser_error_unknown_distribution() {
+        assert_eq!(
+            parse("a{2~Foo(0.5)}"),
+            Err(CompileError::UnknownDistribution(
+                Span::new(4, 7),
+                "Foo".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parser_error_unknown_class() {
+        assert!(matches!(
+            parse("[z-a]"),
+            Err(CompileError::InvalidClass(_, _))
+        ));
+        assert_eq!(
+            parse("a[:foo:]"),
+            Err(CompileError::UnknownClass(
+                Span::new(1, 8),
+                "[:foo:]".to_string()
+            ))
+        );
+        assert!(matches!(
+            parse("[a[:foo:]~Geo(0.5)]"),
+            Err(CompileError::UnknownClass(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_parser_error_invalid_parameter() {
+        assert_eq!(
+            parse("a{2~Geo(1.5)}"),
+            Err(CompileError::InvalidParameter(
+                Span::new(8, 11),
+                "p must be in (0, 1]".to_string()
+            ))
+        );
+        assert!(matches!(
+            parse("a{2~Geo(0.0)}"),
+            Err(CompileError::InvalidParameter(_, _))
+        ));
+        assert!(matches!(
+            parse("[ab~Bin(2.0)]"),
+            Err(CompileError::InvalidParameter(_, _))
+        ));
+        assert!(matches!(
+            parse("[ab~Cat(a=0.7,b=0.7)]"),
+            Err(CompileError::InvalidParameter(_, _))
+        ));
+        assert!(matches!(
+            parse("[ab~Cat(c=0.5)]"),
+            Err(CompileError::InvalidParameter(_, _))
+        ));
+
+        // weights may round slightly over 1
+        assert!(parse("[ab~Cat(a=0.5000000001,b=0.5)]").is_ok());
+        assert!(parse("(a|b)~Cat(0.5000000001,0.5)").is_ok());
+        assert!(matches!(
+            parse("[ab~Cat(a=0.6,b=0.5)]"),
+            Err(CompileError::InvalidParameter(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_parser_error_invalid_distribution() {
+        assert!(matches!(
+            parse("a{2~Cat(a=0.5)}"),
+            Err(CompileError::InvalidDistribution(_, _))
+        ));
+        assert!(matches!(
+            parse("a{2~Geo(0.5,0.5)}"),
+            Err(CompileError::InvalidDistribution(_, _))
+        ));
+        assert!(matches!(
+            parse("a{0~Zipf(1.0)}"),
+            Err(CompileError::InvalidParameter(_, _))
+        ));
+        assert_eq!(
+            parse("[ab~Cat(0.5,0.5)]"),
+            Err(CompileError::InvalidDistribution(
+                Span::new(8, 11),
+                "Cat weighs the members of a class by name, i.e. [ab~Cat(a=0.5,b=0.5)]".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parser_weighted_alternation() {
+        let ast = parse("(?:a|b|c)~Cat(0.5,0.25)").unwrap();
+        match &ast[0].kind {
+            Kind::Alternation(_, _, Some(dist)) => {
+                assert_eq!(*dist, Dist::Categorical(vec![0.0, 0.5, 0.25, 0.25]).index())
+            }
+            kind => panic!("{} is not a weighted alternation", kind),
+        }
+        assert_eq!(ast_as_str(parse("(a|b)~Cat").unwrap()), "(a|b~Cat)");
+
+        // weighing nothing, or more branches than there are
+        for source in ["(ab)~Cat(0.5)", "(a|b)~Geo(0.5)", "(a|b)~Cat(0.2,0.3,0.5)"] {
+            assert!(matches!(
+                parse(source),
+                Err(CompileError::InvalidDistribution(_, _))
+            ));
+        }
+        assert!(matches!(
+            parse("(a|b)~Cat(0.8,0.3)"),
+            Err(CompileError::InvalidParameter(_, _))
+        ));
+
+        // branches without a weight must be left some probability
+        assert_eq!(
+            parse("(a|b|c)~Cat(0.5,0.5)"),
+            Err(CompileError::InvalidParameter(
+                Span::new(7, 20),
+                "no probability is left for the branches without a weight".to_string()
+            ))
+        );
+        assert!(parse("(a|b)~Cat(0.5,0.5)").is_ok());
+    }
+
+    #[test]
+    fn test_parser_error_invalid_quantifier() {
+        assert_eq!(
+            parse("a{}"),
+            Err(CompileError::InvalidQuantifier(
+                Span::new(2, 2),
+                "expected a repetition count".to_string()
+            ))
+        );
+        assert!(matches!(
+            parse("a{99999999999999999999999}"),
+            Err(CompileError::InvalidQuantifier(_, _))
+        ));
+        assert!(matches!(
+            parse("a{6,2}"),
+            Err(CompileError::InvalidQuantifier(_, _))
+        ));
+        assert!(matches!(
+            parse("a{,2}"),
+            Err(CompileError::InvalidQuantifier(_, _))
+        ));
+        assert!(matches!(
+            parse("a{2,~Bin(0.5)}"),
+            Err(CompileError::InvalidDistribution(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_dist_spans() {
+        let source = "a{1~Geo(0.5)}[ab~Cat]b{2,3~Const(0.1)}";
+        let spans = dist_spans(source).unwrap();
+        let dists: Vec<&str> = spans.iter().map(|s| &source[s.start..s.end]).collect();
+        assert_eq!(dists, vec!["~Geo(0.5)", "~Cat", "~Const(0.1)"]);
+        assert_eq!(dist_spans("ab").unwrap(), vec![]);
+        // distributions over alternatives close their group
+        assert_eq!(
+            dist_spans("(a|[ab~Cat])~Cat(0.5)").unwrap(),
+            vec![Span::new(6, 10), Span::new(12, 21)]
+        );
+        assert!(dist_spans("a{").is_err());
+    }
 }
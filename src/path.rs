@@ -0,0 +1,199 @@
+use crate::{ast::Kind, regex_state::Token};
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Most likely path of an input through the NFA, i.e. its Viterbi alignment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    /// Log-likelihood of the path
+    pub log_likelihood: f64,
+    /// One step per input token, including the start and end of input
+    pub steps: Vec<PathStep>,
+}
+
+/// Token of the input and the NFA state that consumed it
+///
+/// Tokens before and after the match are consumed by the start and terminal states.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathStep {
+    pub token: Token,
+    /// Index of the state in the NFA
+    pub state: usize,
+    pub kind: Kind,
+    /// Log probability the step multiplies into the path
+    pub log_factor: f64,
+    /// Quantifiers the path exits after the step
+    pub repetitions: Vec<Repetition>,
+    /// Group boundaries the path passes after the step
+    pub boundaries: Vec<Boundary>,
+    /// Weighted splits between alternatives the path passes after the step
+    pub branches: Vec<Branch>,
+}
+
+/// Number of times the path repeated a quantified expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repetition {
+    /// Index of the quantifier state in the NFA
+    pub state: usize,
+    pub kind: Kind,
+    pub count: u64,
+}
+
+/// Opening or closing of a capturing group on the path
+#[derive(Debug, Clone, PartialEq)]
+pub struct Boundary {
+    /// Index of the group open or close state in the NFA
+    pub state: usize,
+    pub kind: Kind,
+    /// Log-likelihood of the path up to the boundary
+    pub log_likelihood: f64,
+}
+
+/// Side of a weighted split between alternatives the path takes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+    /// Index of the split state in the NFA
+    pub state: usize,
+    pub is_left: bool,
+}
+
+/// Text a capturing group matched on the path
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    /// Number of the group, from 1 in the order groups open in source
+    pub group: usize,
+    pub name: Option<String>,
+    /// Byte offsets into the input
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    /// Log-likelihood of the steps inside the group
+    pub log_likelihood: f64,
+    /// Share of the log-likelihood of the path that falls inside the group
+    pub share: f64,
+}
+
+impl Path {
+    /// Captures of the groups the path passes through, ordered by group number
+    ///
+    /// A group repeated by a quantifier captures its last repetition.
+    pub fn captures(&self) -> Vec<Capture> {
+        let mut input = String::new();
+        // Where each group last opened, with the log-likelihood there
+        let mut opened: BTreeMap<usize, (usize, f64, Option<String>)> = BTreeMap::new();
+        let mut captures: BTreeMap<usize, Capture> = BTreeMap::new();
+
+        for step in self.steps.iter() {
+            if let Kind::Literal(c) = step.token {
+                input.push(c);
+            }
+            for boundary in step.boundaries.iter() {
+                match &boundary.kind {
+                    Kind::GroupOpen(group, name) => {
+                        let open = (input.len(), boundary.log_likelihood, name.clone());
+                        opened.insert(*group, open);
+                    }
+                    Kind::GroupClose(group) => {
+                        let (start, log_likelihood, name) = opened[group].clone();
+                        let log_likelihood = boundary.log_likelihood - log_likelihood;
+                        let capture = Capture {
+                            group: *group,
+                            name,
+                            start,
+                            end: input.len(),
+                            text: input[start..].to_string(),
+                            log_likelihood,
+                            share: match self.log_likelihood < 0.0 {
+                                // both are negative, abs avoids printing -0 for a group with no cost
+                                true => f64::abs(log_likelihood / self.log_likelihood),
+                                false => 0.0,
+                            },
+                        };
+                        captures.insert(*group, capture);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        captures.into_values().collect()
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in self.steps.iter() {
+            writeln!(f, "{}", step)?;
+        }
+        write!(f, "log-likelihood\t{:.5}", self.log_likelihood)
+    }
+}
+
+impl fmt::Display for PathStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let token = match self.token {
+            Kind::Start => "<start>".to_string(),
+            Kind::Terminal => "<end>".to_string(),
+            Kind::Literal(c) => format!("{:?}", c),
+            ref token => token.to_string(),
+        };
+        let kind = match self.kind {
+            Kind::Start => "<skip>".to_string(),
+            Kind::Terminal => "<end>".to_string(),
+            ref kind => kind.to_string(),
+        };
+        write!(
+            f,
+            "{}\t#{} {}\t{:.5}",
+            token,
+            self.state,
+            kind,
+            self.log_factor.exp()
+        )?;
+        if !self.repetitions.is_empty() {
+            write!(f, "\t{}", self.repetitions.iter().join(" "))?;
+        }
+        if !self.boundaries.is_empty() {
+            write!(f, "\t{}", self.boundaries.iter().join(" "))?;
+        }
+        if !self.branches.is_empty() {
+            write!(f, "\t{}", self.branches.iter().join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Repetition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            Kind::Quantifier(c) => write!(f, "#{} {}={}", self.state, c, self.count),
+            ref kind => write!(f, "#{} {{{}}}={}", self.state, kind, self.count),
+        }
+    }
+}
+
+impl fmt::Display for Boundary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{} {}", self.state, self.kind)
+    }
+}
+
+impl fmt::Display for Branch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.is_left {
+            true => write!(f, "#{} left", self.state),
+            false => write!(f, "#{} right", self.state),
+        }
+    }
+}
+
+impl fmt::Display for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.name.as_deref().unwrap_or("-");
+        write!(
+            f,
+            "{}\t{}\t{}..{}\t{:.5}\t{:?}",
+            self.group, name, self.start, self.end, self.share, self.text
+        )
+    }
+}
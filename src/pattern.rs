@@ -0,0 +1,398 @@
+use crate::{
+    ast::AstNode,
+    background::Background,
+    error::CompileError,
+    find::{Match, Matches},
+    fit::{fit, Fit},
+    nfa,
+    nfa::State,
+    parser,
+    path::{Capture, Path},
+    regex::{best_path, find_at, match_likelihood_with},
+    sample,
+    semiring::Semiring,
+};
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+
+/// A compiled probabilistic regular expression
+///
+/// The pattern keeps its source and AST around next to the NFA,
+/// so that it can be inspected after compilation.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    ast: Vec<AstNode>,
+    nfa: Vec<State>,
+    semiring: Semiring,
+}
+
+impl Pattern {
+    /// Compile a pattern from source
+    pub fn new(source: &str) -> Result<Pattern, CompileError> {
+        let ast = parser::parse(source)?;
+        let nfa = nfa::asts_to_nfa(ast.clone());
+        Ok(Pattern {
+            source: source.to_string(),
+            ast,
+            nfa,
+            semiring: Semiring::default(),
+        })
+    }
+
+    /// Use semiring to merge competing paths when scoring input
+    ///
+    /// Defaults to `Semiring::MaxProduct`, the probability of the best path.
+    pub fn with_semiring(mut self, semiring: Semiring) -> Pattern {
+        self.semiring = semiring;
+        self
+    }
+
+    /// Let `.` and negated classes without a distribution score characters by background
+    ///
+    /// Without one they match any character with probability one.
+    pub fn with_background(mut self, background: &Background) -> Pattern {
+        background.apply(&mut self.nfa);
+        self
+    }
+
+    /// Likelihood of input under the pattern, or None if it does not match
+    ///
+    /// Scored in log space like `log_likelihood`, so a long input that matches may
+    /// have a likelihood that rounds to zero, but is never None.
+    pub fn likelihood(&self, input: &str) -> Option<f64> {
+        self.log_likelihood(input).map(f64::exp)
+    }
+
+    /// Natural log of the likelihood, or None if input does not match
+    ///
+    /// Scoring runs in log space, so long inputs do not underflow to zero.
+    pub fn log_likelihood(&self, input: &str) -> Option<f64> {
+        match_likelihood_with(&self.nfa, &input.to_string(), self.semiring, true, false)
+    }
+
+    /// Most likely path of input through the pattern, or None if it does not match
+    ///
+    /// The path tells which state consumed each character of input and with what
+    /// probability, regardless of the semiring of the pattern.
+    pub fn best_path(&self, input: &str) -> Option<Path> {
+        best_path(&self.nfa, &input.to_string())
+    }
+
+    /// Text captured by each group on the most likely path, or None if input does not match
+    ///
+    /// Each capture also tells how much of the log-likelihood of the path falls inside it.
+    pub fn captures(&self, input: &str) -> Option<Vec<Capture>> {
+        self.best_path(input).map(|path| path.captures())
+    }
+
+    /// Most likely match of the pattern inside text, or None if there is none
+    ///
+    /// Unlike `likelihood`, which scores the whole text, this also tells where the
+    /// match is. Ties go to the leftmost, then the longest match.
+    pub fn find(&self, text: &str) -> Option<Match> {
+        find_at(&self.nfa, text, 0, self.semiring)
+    }
+
+    /// Successive non-overlapping matches of the pattern inside text
+    pub fn find_iter<'p, 't>(&'p self, text: &'t str) -> Matches<'p, 't> {
+        Matches::new(&self.nfa, self.semiring, text)
+    }
+
+    /// Draw a random string from the pattern, read as a generative model
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        sample::sample(&self.nfa, rng)
+    }
+
+    /// Re-estimate the parameters of the distributions from the lines of corpus
+    ///
+    /// See `fit::fit`, which iterates at most max_iterations times.
+    pub fn fit<S: AsRef<str>>(
+        &self,
+        corpus: &[S],
+        max_iterations: usize,
+    ) -> Result<Fit, CompileError> {
+        fit(self, corpus, max_iterations)
+    }
+
+    /// Like `likelihood`, but prints each step of the simulation to stdout
+    pub fn visualize(&self, input: &str) -> Option<f64> {
+        match_likelihood_with(&self.nfa, &input.to_string(), self.semiring, false, true)
+    }
+
+    /// True if input matches with a non-zero likelihood, however small
+    pub fn is_match(&self, input: &str) -> bool {
+        self.log_likelihood(input).is_some()
+    }
+
+    /// Source the pattern was compiled from
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Semiring used to merge competing paths
+    pub fn semiring(&self) -> Semiring {
+        self.semiring
+    }
+
+    /// Abstract syntax trees of the pattern
+    pub fn ast(&self) -> &[AstNode] {
+        &self.ast
+    }
+
+    /// States of the compiled NFA
+    pub fn nfa(&self) -> &[State] {
+        &self.nfa
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = CompileError;
+
+    fn from_str(source: &str) -> Result<Pattern, CompileError> {
+        Pattern::new(source)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::Kind;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_pattern_likelihood() {
+        let pattern = Pattern::new("^a{2~Geo(0.5)}b$").unwrap();
+
+        assert_eq!(pattern.likelihood("ab"), None);
+        assert_eq!(pattern.likelihood("aab"), Some(0.5));
+        assert_eq!(pattern.likelihood("aaab"), Some(0.25));
+    }
+
+    #[test]
+    fn test_pattern_semiring() {
+        // "ab" is matched both by (a)(b) and by (ab)
+        let pattern = Pattern::new("^(a|ab|b)+$").unwrap();
+        assert_eq!(pattern.likelihood("ab"), Some(1.0));
+
+        let pattern = pattern.with_semiring(Semiring::SumProduct);
+        assert_eq!(pattern.semiring(), Semiring::SumProduct);
+        assert_eq!(pattern.likelihood("ab"), Some(2.0));
+        assert_eq!(pattern.likelihood("a"), Some(1.0));
+    }
+
+    #[test]
+    fn test_pattern_semiring_weighted() {
+        let source = "^([ab~Cat(a=0.2,b=0.8)]|a{1~Const(0.5)})$";
+        let pattern = Pattern::new(source).unwrap();
+        assert_eq!(pattern.likelihood("a"), Some(0.5));
+        assert_eq!(pattern.likelihood("b"), Some(0.8));
+
+        let pattern = pattern.with_semiring(Semiring::SumProduct);
+        assert_relative_eq!(pattern.likelihood("a").unwrap(), 0.2 + 0.5);
+        assert_eq!(pattern.likelihood("b"), Some(0.8));
+    }
+
+    #[test]
+    fn test_pattern_background() {
+        // smoothed to a = 1/2, b = 1/3 and 1/6 shared by all other characters
+        let background = Background::from_weights([('a', 2.0), ('b', 1.0)]);
+        let pattern = Pattern::new("^.[^a]$")
+            .unwrap()
+            .with_background(&background);
+        assert_relative_eq!(pattern.likelihood("ab").unwrap(), 1.0 / 6.0);
+        assert_relative_eq!(
+            pattern.likelihood("zz").unwrap(),
+            background.p('z') * background.p('z')
+        );
+        assert!(background.p('z') < 1e-6);
+        assert_eq!(pattern.likelihood("aa"), None);
+
+        // classes with a distribution keep it
+        let pattern = Pattern::new("^[^a~Cat(.=0.5)]$")
+            .unwrap()
+            .with_background(&background);
+        assert_eq!(pattern.likelihood("b"), Some(0.5));
+    }
+
+    #[test]
+    fn test_pattern_log_likelihood() {
+        let pattern = Pattern::new("^a{2~Geo(0.5)}b$").unwrap();
+        assert_eq!(pattern.log_likelihood("ab"), None);
+        assert_relative_eq!(pattern.log_likelihood("aab").unwrap(), 0.5_f64.ln());
+        assert_relative_eq!(pattern.log_likelihood("aaab").unwrap(), 0.25_f64.ln());
+
+        let pattern = Pattern::new("^(a|ab|b)+$").unwrap();
+        let pattern = pattern.with_semiring(Semiring::SumProduct);
+        assert_relative_eq!(pattern.log_likelihood("ab").unwrap(), 2.0_f64.ln());
+    }
+
+    #[test]
+    fn test_pattern_log_likelihood_long_input() {
+        // 0.25^2000 underflows in linear space, but still matches
+        let pattern = Pattern::new("^[ab~Geo(0.5)]+$").unwrap();
+        let input = "b".repeat(2000);
+        assert_eq!(pattern.likelihood(&input), Some(0.0));
+        assert!(pattern.is_match(&input));
+        assert_relative_eq!(
+            pattern.log_likelihood(&input).unwrap(),
+            2000. * 0.25_f64.ln(),
+            max_relative = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_pattern_log_likelihood_deep_tail() {
+        // far past the mean, each repetition takes less than the last
+        use statrs::function::factorial::ln_factorial;
+        let pattern = Pattern::new("^a{0~Pois(2.0)}$").unwrap();
+        for n in [40, 60, 150] {
+            assert_relative_eq!(
+                pattern.log_likelihood(&"a".repeat(n)).unwrap(),
+                n as f64 * 2.0_f64.ln() - 2.0 - ln_factorial(n as u64),
+                max_relative = 1e-9
+            );
+        }
+    }
+
+    #[test]
+    fn test_pattern_best_path() {
+        let pattern = Pattern::new("^a[bc~Cat(b=0.2,c=0.8)]+$").unwrap();
+        let path = pattern.best_path("abc").unwrap();
+        assert_relative_eq!(path.log_likelihood, (0.2_f64 * 0.8).ln());
+        assert_eq!(
+            path.to_string(),
+            "<start>\t#0 ^\t1.00000\n\
+             'a'\t#1 a\t1.00000\n\
+             'b'\t#3 [bc]\t0.20000\n\
+             'c'\t#3 [bc]\t0.80000\n\
+             <end>\t#4 $\t1.00000\n\
+             log-likelihood\t-1.83258"
+        );
+        assert_eq!(pattern.best_path("abd"), None);
+    }
+
+    #[test]
+    fn test_pattern_captures() {
+        let pattern = Pattern::new("^(?<key>[a-z]+) ([0-9]{1~Geo(0.5)})(?:-)?$").unwrap();
+        let captures = pattern.captures("ab 12-").unwrap();
+        assert_eq!(captures.len(), 2);
+
+        assert_eq!(captures[0].group, 1);
+        assert_eq!(captures[0].name.as_deref(), Some("key"));
+        assert_eq!((captures[0].start, captures[0].end), (0, 2));
+        assert_eq!(captures[0].text, "ab");
+        assert_eq!(captures[0].log_likelihood, 0.0);
+
+        assert_eq!(captures[1].group, 2);
+        assert_eq!(captures[1].name, None);
+        assert_eq!(captures[1].text, "12");
+        assert_relative_eq!(captures[1].log_likelihood, 0.25_f64.ln());
+        assert_relative_eq!(captures[1].share, 1.0);
+
+        assert_eq!(pattern.captures("ab "), None);
+    }
+
+    #[test]
+    fn test_pattern_captures_repeated() {
+        // the last repetition is captured, groups not on the path are left out
+        let pattern = Pattern::new("^((a)|(b[bc~Cat(b=0.2,c=0.8)]))+$").unwrap();
+        let captures = pattern.captures("abcabb").unwrap();
+        let groups: Vec<(usize, &str)> = captures.iter().map(|c| (c.group, &c.text[..])).collect();
+        assert_eq!(groups, vec![(1, "bb"), (2, "a"), (3, "bb")]);
+        assert_relative_eq!(captures[0].share, 0.2_f64.ln() / (0.8_f64 * 0.2).ln());
+
+        let captures = pattern.captures("a").unwrap();
+        let groups: Vec<(usize, &str)> = captures.iter().map(|c| (c.group, &c.text[..])).collect();
+        assert_eq!(groups, vec![(1, "a"), (2, "a")]);
+    }
+
+    #[test]
+    fn test_pattern_captures_empty() {
+        let pattern = Pattern::new("x(a*)y").unwrap();
+        let captures = pattern.captures("zxyz").unwrap();
+        assert_eq!((captures[0].start, captures[0].end), (2, 2));
+        assert_eq!(captures[0].text, "");
+    }
+
+    #[test]
+    fn test_pattern_find() {
+        let pattern = Pattern::new("abc").unwrap();
+        let m = pattern.find("xxabcyy").unwrap();
+        assert_eq!((m.start, m.end, m.likelihood()), (2, 5, 1.0));
+        assert_eq!(pattern.find("xxabyy"), None);
+
+        // the likelier of two matches, not the first
+        let pattern = Pattern::new("a{1~Geo(0.5)}b").unwrap();
+        let m = pattern.find("aab xab").unwrap();
+        assert_eq!((m.start, m.end), (1, 3));
+        assert_relative_eq!(m.likelihood(), 0.5);
+        let pattern = Pattern::new("xa{1~Geo(0.5)}b").unwrap();
+        let m = pattern.find("xaab xab").unwrap();
+        assert_eq!((m.start, m.end), (5, 8));
+
+        // byte offsets
+        let m = Pattern::new("b+").unwrap().find("ébb").unwrap();
+        assert_eq!((m.start, m.end), (2, 4));
+    }
+
+    #[test]
+    fn test_pattern_find_semiring() {
+        // "ab" is matched both by (a)(b) and by (ab)
+        let pattern = Pattern::new("(a|ab|b)+").unwrap();
+        assert_eq!(pattern.find("xabx").unwrap().likelihood(), 1.0);
+        let pattern = pattern.with_semiring(Semiring::SumProduct);
+        assert_relative_eq!(pattern.find("xabx").unwrap().likelihood(), 2.0);
+    }
+
+    #[test]
+    fn test_pattern_find_iter() {
+        let pattern = Pattern::new("[0-9]{1,3~Geo(0.5)}").unwrap();
+        let text = "GET /a 200 12ms";
+        let matches: Vec<&str> = pattern.find_iter(text).map(|m| &text[m.range()]).collect();
+        assert_eq!(matches, vec!["2", "0", "0", "1", "2"]);
+
+        let pattern = Pattern::new("[0-9]+").unwrap();
+        let matches: Vec<&str> = pattern.find_iter(text).map(|m| &text[m.range()]).collect();
+        assert_eq!(matches, vec!["200", "12"]);
+    }
+
+    #[test]
+    fn test_pattern_is_match() {
+        let pattern = Pattern::new("^[ab~Cat(a=1.0)]$").unwrap();
+
+        assert!(pattern.is_match("a"));
+        assert!(!pattern.is_match("b"));
+        assert!(!pattern.is_match("c"));
+
+        // 0.5^1500 underflows in linear space
+        let pattern = Pattern::new("^a{1~Geo(0.5)}$").unwrap();
+        assert!(pattern.is_match(&"a".repeat(1500)));
+        assert!(pattern.likelihood(&"a".repeat(1500)).is_some());
+    }
+
+    #[test]
+    fn test_pattern_accessors() {
+        let pattern: Pattern = "ab".parse().unwrap();
+
+        assert_eq!(pattern.as_str(), "ab");
+        assert_eq!(pattern.to_string(), "ab");
+        assert_eq!(pattern.ast().last().unwrap().kind, Kind::Terminal);
+        assert_eq!(pattern.nfa().first().unwrap().kind, Kind::Start);
+    }
+
+    #[test]
+    fn test_pattern_invalid() {
+        assert!(matches!(
+            Pattern::new("a{"),
+            Err(CompileError::Syntax(_, _))
+        ));
+    }
+}
@@ -1,77 +1,344 @@
-use colored::Colorize;
-
 use crate::{
-    ast::{AstNode, Kind},
-    distribution::Dist,
+    ast::Kind,
+    find::Match,
     nfa::State,
-    regex_state::{evaluate_state, initial_state, terminal_state_p, Token, Tokens, Transition},
+    path::{Boundary, Branch, Path, PathStep, Repetition},
+    regex_state::{
+        evaluate_state, initial_state, terminal_state_p, Counters, Passed, States, Thread, Token,
+        Tokens, Transition,
+    },
+    semiring::{one, Semiring},
     visualization,
 };
 use itertools::Itertools;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+
+pub fn match_likelihood<T>(nfa: &[State], input: &T, debug_visualize: bool) -> Option<f64>
+where
+    T: Into<Tokens> + Clone,
+{
+    match_likelihood_with(nfa, input, Semiring::MaxProduct, false, debug_visualize)
+}
 
-pub fn match_likelihood<T>(nfa: &Vec<State>, input: &T, debug_visualize: bool) -> Option<f64>
+/// Likelihood of input, with competing paths merged by semiring
+///
+/// If log is set, the simulation runs in log space and the log-likelihood is returned,
+/// which does not underflow on long inputs.
+pub fn match_likelihood_with<T>(
+    nfa: &[State],
+    input: &T,
+    semiring: Semiring,
+    log: bool,
+    debug_visualize: bool,
+) -> Option<f64>
 where
     T: Into<Tokens> + Clone,
 {
-    let mut states = initial_state(nfa, false);
-    let mut counts = HashMap::new();
-    let tokens: Vec<Token> = input.clone().into().as_vec();
+    let mut states = initial_state(nfa, false, log);
+    let tokens: Vec<Token> = input.clone().into().into_vec();
 
     for token in tokens.iter() {
         if debug_visualize {
-            visualization::debug_print(&states, &counts, nfa, &token);
+            visualization::debug_print(&states, nfa, token, log);
         }
-        states = step_states(states, &counts, token, nfa);
-        counts = add_counts(&states, &counts);
+        states = step_states(states, token, nfa, semiring, log);
     }
-    return terminal_state_p(&states, &nfa);
+    terminal_state_p(&states, nfa, semiring, log)
 }
 
-fn step_states(
-    states: HashMap<usize, f64>,
-    counts: &HashMap<usize, u64>,
-    token: &Token,
-    nfa: &Vec<State>,
-) -> HashMap<usize, f64> {
-    let mut next: HashMap<usize, f64> = HashMap::new();
-    for (state, p) in states.iter() {
-        let state = Some(*state);
-        let transitions = evaluate_state(state, token, *p, nfa, counts, &states, false);
-        for transition in transitions {
-            if let Transition(Some(out), new_p) = transition {
-                if new_p == 0.0 {
-                    continue;
-                }
-                let old_p = next.entry(out).or_insert(new_p);
-                *old_p = f64::max(*old_p, new_p);
+/// Most likely path of input through the NFA, or None if it does not match
+///
+/// Runs the simulation with the max-product semiring in log space, keeping a
+/// back pointer to the best previous thread for every thread of every step.
+pub fn best_path<T>(nfa: &[State], input: &T) -> Option<Path>
+where
+    T: Into<Tokens> + Clone,
+{
+    let mut states = initial_state(nfa, false, true);
+    let tokens: Vec<Token> = input.clone().into().into_vec();
+    let mut history = Vec::with_capacity(tokens.len());
+
+    for token in tokens.iter() {
+        let (next, pointers) = step_states_traced(states, token, nfa, Semiring::MaxProduct, true);
+        history.push(pointers);
+        states = next;
+    }
+    let idx_terminal = nfa.len() - 1;
+    let (mut thread, log_likelihood) = states
+        .into_iter()
+        .filter(|(Thread(idx, _), _)| *idx == idx_terminal)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+    // Follow the back pointers from the terminal thread to the start
+    let mut steps = Vec::with_capacity(tokens.len());
+    let mut repeats = Vec::with_capacity(tokens.len());
+    for (token, pointers) in tokens.into_iter().zip(history.iter()).rev() {
+        let pointer = &pointers[&thread];
+        steps.push(PathStep {
+            token,
+            state: pointer.from.0,
+            kind: nfa[pointer.from.0].kind.clone(),
+            log_factor: pointer.p - pointer.p_from,
+            repetitions: pointer
+                .passed
+                .iter()
+                .filter_map(|passed| match *passed {
+                    Passed::Exit(state, count) => Some(Repetition {
+                        state,
+                        kind: nfa[state].kind.clone(),
+                        count,
+                    }),
+                    Passed::Group(_, _) | Passed::Repeat(_) | Passed::Branch(_, _) => None,
+                })
+                .collect(),
+            boundaries: pointer
+                .passed
+                .iter()
+                .filter_map(|passed| match *passed {
+                    Passed::Group(state, log_likelihood) => Some(Boundary {
+                        state,
+                        kind: nfa[state].kind.clone(),
+                        log_likelihood,
+                    }),
+                    Passed::Exit(_, _) | Passed::Repeat(_) | Passed::Branch(_, _) => None,
+                })
+                .collect(),
+            branches: pointer
+                .passed
+                .iter()
+                .filter_map(|passed| match *passed {
+                    Passed::Branch(state, is_left) => Some(Branch { state, is_left }),
+                    _ => None,
+                })
+                .collect(),
+        });
+        repeats.push(
+            pointer
+                .passed
+                .iter()
+                .filter_map(|passed| match *passed {
+                    Passed::Repeat(state) => Some(state),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        );
+        thread = pointer.from.clone();
+    }
+    steps.reverse();
+    repeats.reverse();
+
+    // Threads stop counting past what the distribution tells apart, add the repetitions back
+    let mut uncounted: HashMap<usize, u64> = HashMap::new();
+    for (step, repeated) in steps.iter_mut().zip(repeats) {
+        for state in repeated {
+            *uncounted.entry(state).or_default() += 1;
+        }
+        for repetition in step.repetitions.iter_mut() {
+            repetition.count += uncounted.remove(&repetition.state).unwrap_or(0);
+        }
+    }
+
+    Some(Path {
+        log_likelihood,
+        steps,
+    })
+}
+
+/// Most likely match in text that starts at byte offset from or later, or None
+///
+/// Unlike `match_likelihood`, which lets the start and terminal states skip the
+/// text around the match, the threads of every start position are kept apart, so
+/// that the span of the match is known. Paths of the same span are merged by
+/// semiring. Ties between spans go to the leftmost, then the longest one.
+///
+/// The simulation starts at from, with the character before it, if any, as the
+/// token the start state consumes. Under the max-product semiring a thread is
+/// only kept for the start that reaches it most likely, as the other starts can
+/// not end a better match from there.
+pub fn find_at(nfa: &[State], text: &str, from: usize, semiring: Semiring) -> Option<Match> {
+    // A match starts on a character boundary
+    let from = (from..=text.len()).find(|&i| text.is_char_boundary(i))?;
+    let rest = &text[from..];
+    let offsets: Vec<usize> = rest
+        .char_indices()
+        .map(|(i, _)| from + i)
+        .chain([text.len()])
+        .collect();
+    let before = match text[..from].chars().next_back() {
+        Some(c) => Kind::Literal(c),
+        None => Kind::Start,
+    };
+    let tokens: Vec<Token> = [before]
+        .into_iter()
+        .chain(rest.chars().map(Kind::Literal))
+        .chain([Kind::Terminal])
+        .collect();
+    let idx_terminal = nfa.len() - 1;
+    let n_chars = offsets.len() - 1;
+
+    // Threads by the index of the character their match starts at
+    let mut starts: Vec<(usize, States)> = Vec::new();
+    let mut best: Option<(usize, usize, f64)> = None;
+    let mut end_match = |start: usize, end: usize, states: &mut States| {
+        let ended: Vec<Thread> = states
+            .keys()
+            .filter(|Thread(idx, _)| *idx == idx_terminal)
+            .cloned()
+            .collect();
+        let p = ended
+            .iter()
+            .filter_map(|thread| states.remove(thread))
+            .reduce(|a, b| semiring.add(a, b, true));
+        let is_better = match (p, best) {
+            (None, _) => false,
+            // the start state may enter with a zero likelihood, i.e. a{1~Geo} at the end of text
+            (Some(p), _) if p == semiring.zero(true) => false,
+            (Some(_), None) => true,
+            (Some(p), Some((best_start, best_end, best_p))) => {
+                p > best_p || (p == best_p && (start, best_end) < (best_start, end))
+            }
+        };
+        if is_better {
+            best = Some((start, end, p.unwrap()));
+        }
+    };
+
+    for (i, token) in tokens.iter().enumerate() {
+        // Token i is the character before index i, or the end of text
+        let end = usize::min(i, n_chars);
+        for (start, states) in starts.iter_mut() {
+            *states = step_states(std::mem::take(states), token, nfa, semiring, true);
+            end_match(*start, end, states);
+        }
+        if semiring == Semiring::MaxProduct {
+            keep_most_likely_starts(&mut starts);
+        }
+        starts.retain(|(_, states)| !states.is_empty());
+
+        // The start state enters the expression after each token, but the end of text
+        if i > n_chars {
+            continue;
+        }
+        let mut states = States::new();
+        let transitions = evaluate_state(
+            Some(0),
+            token,
+            one(true),
+            &Counters::new(),
+            nfa,
+            &[],
+            false,
+            true,
+        );
+        for Transition(thread, p, _) in transitions {
+            if thread.0 != 0 && p != semiring.zero(true) {
+                let old_p = states.entry(thread).or_insert(semiring.zero(true));
+                *old_p = semiring.add(*old_p, p, true);
+            }
+        }
+        end_match(i, i, &mut states);
+        if !states.is_empty() {
+            starts.push((i, states));
+        }
+    }
+
+    best.map(|(start, end, log_likelihood)| Match {
+        start: offsets[start],
+        end: offsets[end],
+        log_likelihood,
+    })
+}
+
+/// Drop each thread from all starts but the one with the highest probability for it
+///
+/// Starts are in order, so ties keep the leftmost start.
+fn keep_most_likely_starts(starts: &mut [(usize, States)]) {
+    let mut best: HashMap<Thread, (usize, f64)> = HashMap::new();
+    for (i, (_, states)) in starts.iter().enumerate() {
+        for (thread, p) in states.iter() {
+            if best.get(thread).is_none_or(|(_, best_p)| p > best_p) {
+                best.insert(thread.clone(), (i, *p));
             }
         }
     }
-    next
+    for (i, (_, states)) in starts.iter_mut().enumerate() {
+        states.retain(|thread, _| best[thread].0 == i);
+    }
+}
+
+/// Best transition into a thread, from the thread that consumed the token
+#[derive(Debug, Clone, PartialEq)]
+struct BackPointer {
+    from: Thread,
+    p_from: f64,
+    p: f64,
+    passed: Vec<Passed>,
+}
+
+/// Advance every thread by token, merging threads that meet by semiring
+fn step_states(
+    states: States,
+    token: &Token,
+    nfa: &[State],
+    semiring: Semiring,
+    log: bool,
+) -> States {
+    step_states_traced(states, token, nfa, semiring, log).0
 }
 
-fn add_counts(states: &HashMap<usize, f64>, counts: &HashMap<usize, u64>) -> HashMap<usize, u64> {
-    let mut updated: HashMap<usize, u64> = counts.clone();
-    for (state, p) in states.iter() {
-        if *p > 0.0 {
-            updated.entry(*state).and_modify(|n| *n += 1).or_insert(1);
+/// Like `step_states`, but also returns the back pointer of each next state
+fn step_states_traced(
+    states: States,
+    token: &Token,
+    nfa: &[State],
+    semiring: Semiring,
+    log: bool,
+) -> (States, HashMap<Thread, BackPointer>) {
+    let mut next: States = HashMap::new();
+    let mut pointers: HashMap<Thread, BackPointer> = HashMap::new();
+    // Visit threads in order, so that ties between paths are broken consistently
+    for (thread, p) in states.iter().sorted_by(|(a, _), (b, _)| a.cmp(b)) {
+        let Thread(state, counters) = thread;
+        let transitions = evaluate_state(Some(*state), token, *p, counters, nfa, &[], false, log);
+        for Transition(out, new_p, passed) in transitions {
+            if new_p == semiring.zero(log) {
+                continue;
+            }
+            let old_p = next.entry(out.clone()).or_insert(semiring.zero(log));
+            *old_p = semiring.add(*old_p, new_p, log);
+
+            if pointers.get(&out).is_none_or(|pointer| new_p > pointer.p) {
+                let pointer = BackPointer {
+                    from: thread.clone(),
+                    p_from: *p,
+                    p: new_p,
+                    passed,
+                };
+                pointers.insert(out, pointer);
+            }
         }
     }
-    updated
+    (next, pointers)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{ast::Kind, compile, distribution::Dist, regex_state::Counters};
+    use approx::assert_relative_eq;
 
-    #[test]
-    fn test_add_counts() {
-        let nfa = vec![State::start(Some(1)), State::literal('a', (Some(2), None))];
-        let states = initial_state(&nfa, true);
+    /// Threads outside of counted quantifiers
+    fn at(states: &[(usize, f64)]) -> States {
+        states.iter().map(|&(s, p)| (Thread::at(s), p)).collect()
+    }
+
+    /// Thread inside counted quantifiers
+    fn counted(state: usize, counters: &[(usize, u64)]) -> Thread {
+        Thread(state, counters.iter().copied().collect::<Counters>())
+    }
 
-        let counts = add_counts(&states, &HashMap::new());
-        assert_eq!(counts, [(0, 1), (1, 1)].into());
+    fn step(states: States, c: char, nfa: &[State]) -> States {
+        step_states(states, &Kind::Literal(c), nfa, Semiring::MaxProduct, false)
     }
 
     #[test]
@@ -82,18 +349,17 @@ mod test {
             State::literal('b', (Some(3), None)),
             State::terminal(),
         ];
-        let counts = HashMap::new();
-        let states = initial_state(&nfa, true);
-        assert_eq!(states, [(0, 1.0), (1, 1.0)].into());
+        let states = initial_state(&nfa, true, false);
+        assert_eq!(states, at(&[(0, 1.0), (1, 1.0)]));
 
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        assert_eq!(states, [(0, 1.0), (1, 1.0), (2, 1.0)].into());
+        let states = step(states, 'a', &nfa);
+        assert_eq!(states, at(&[(0, 1.0), (1, 1.0), (2, 1.0)]));
 
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        assert_eq!(states, [(0, 1.0), (1, 1.0), (2, 1.0)].into());
+        let states = step(states, 'a', &nfa);
+        assert_eq!(states, at(&[(0, 1.0), (1, 1.0), (2, 1.0)]));
 
-        let states = step_states(states, &counts, &Kind::Literal('b'), &nfa);
-        assert_eq!(states, [(0, 1.0), (1, 1.0), (3, 1.0)].into());
+        let states = step(states, 'b', &nfa);
+        assert_eq!(states, at(&[(0, 1.0), (1, 1.0), (3, 1.0)]));
     }
 
     #[test]
@@ -104,15 +370,14 @@ mod test {
             State::literal('b', (Some(3), None)),
             State::terminal(),
         ];
-        let counts = HashMap::new();
-        let states = initial_state(&nfa, true);
-        assert_eq!(states, [(1, 1.0)].into());
+        let states = initial_state(&nfa, true, false);
+        assert_eq!(states, at(&[(1, 1.0)]));
 
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        assert_eq!(states, [(2, 1.0)].into());
+        let states = step(states, 'a', &nfa);
+        assert_eq!(states, at(&[(2, 1.0)]));
 
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        assert_eq!(states, [].into());
+        let states = step(states, 'a', &nfa);
+        assert_eq!(states, at(&[]));
     }
 
     #[test]
@@ -124,15 +389,98 @@ mod test {
             State::literal('b', (Some(4), None)),
             State::terminal(),
         ];
-        let counts = HashMap::new();
-        let states = initial_state(&nfa, true);
-        assert_eq!(states, [(0, 1.0), (2, 1.0), (3, 1.0)].into());
+        let states = initial_state(&nfa, true, false);
+        assert_eq!(states, at(&[(0, 1.0), (2, 1.0), (3, 1.0)]));
 
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        assert_eq!(states, [(0, 1.0), (2, 1.0), (3, 1.0), (4, 1.0)].into());
+        let states = step(states, 'a', &nfa);
+        assert_eq!(states, at(&[(0, 1.0), (2, 1.0), (3, 1.0), (4, 1.0)]));
 
-        let states = step_states(states, &counts, &Kind::Literal('b'), &nfa);
-        assert_eq!(states, [(0, 1.0), (2, 1.0), (3, 1.0), (4, 1.0)].into());
+        let states = step(states, 'b', &nfa);
+        assert_eq!(states, at(&[(0, 1.0), (2, 1.0), (3, 1.0), (4, 1.0)]));
+    }
+
+    #[test]
+    fn test_step_states_alternation_sum() {
+        let nfa = vec![
+            State::anchor_start(Some(1)),
+            State::split((Some(2), Some(3))),
+            State::literal('a', (Some(4), None)),
+            State::literal('a', (Some(4), None)),
+            State::terminal(),
+        ];
+        let states = initial_state(&nfa, true, false);
+
+        let next = step(states.clone(), 'a', &nfa);
+        assert_eq!(next, at(&[(4, 1.0)]));
+
+        let next = step_states(
+            states,
+            &Kind::Literal('a'),
+            &nfa,
+            Semiring::SumProduct,
+            false,
+        );
+        assert_eq!(next, at(&[(4, 2.0)]));
+    }
+
+    #[test]
+    fn test_best_path() {
+        let nfa = compile("^a{2~Geo(0.5)}b$").unwrap();
+        let path = best_path(&nfa, &"aaab".to_string()).unwrap();
+        assert_relative_eq!(path.log_likelihood, 0.25_f64.ln());
+
+        let tokens: Vec<Token> = path.steps.iter().map(|s| s.token.clone()).collect();
+        assert_eq!(tokens, Tokens::from("aaab".to_string()).into_vec());
+
+        // each a is consumed by the literal, the last one exits the quantifier
+        let literals: Vec<usize> = path.steps[1..4].iter().map(|s| s.state).collect();
+        assert!(literals
+            .iter()
+            .all(|&idx| nfa[idx].kind == Kind::Literal('a')));
+        assert!(path.steps[1].repetitions.is_empty());
+        assert_eq!(path.steps[3].repetitions.len(), 1);
+        assert_eq!(path.steps[3].repetitions[0].count, 3);
+        assert_eq!(path.steps[4].kind, Kind::Literal('b'));
+
+        // factors multiply to the likelihood
+        let total: f64 = path.steps.iter().map(|s| s.log_factor).sum();
+        assert_relative_eq!(total, path.log_likelihood);
+    }
+
+    #[test]
+    fn test_best_path_branches() {
+        let nfa = compile("^(a|b|c)~Cat(0.2,0.3)$").unwrap();
+        let path = best_path(&nfa, &"c".to_string()).unwrap();
+        assert_relative_eq!(path.log_likelihood, 0.5_f64.ln());
+
+        // both splits of the chain are passed on their right before c
+        let branches: Vec<_> = path.steps.iter().flat_map(|s| s.branches.clone()).collect();
+        assert_eq!(branches.len(), 2);
+        assert!(branches.iter().all(|b| !b.is_left));
+        assert!(branches.iter().all(|b| nfa[b.state].kind == Kind::Split));
+
+        // unweighted alternatives leave no trace
+        let nfa = compile("^(a|b)$").unwrap();
+        let path = best_path(&nfa, &"b".to_string()).unwrap();
+        assert!(path.steps.iter().all(|s| s.branches.is_empty()));
+    }
+
+    #[test]
+    fn test_best_path_unanchored() {
+        let nfa = compile("ab").unwrap();
+        let path = best_path(&nfa, &"xaby".to_string()).unwrap();
+
+        let kinds: Vec<Kind> = path.steps.iter().map(|s| s.kind.clone()).collect();
+        assert_eq!(
+            kinds[1..5],
+            [
+                Kind::Start,
+                Kind::Literal('a'),
+                Kind::Literal('b'),
+                Kind::Terminal
+            ]
+        );
+        assert_eq!(best_path(&nfa, &"ba".to_string()), None);
     }
 
     #[test]
@@ -148,22 +496,17 @@ mod test {
             State::literal('b', (Some(4), None)),
             State::terminal(),
         ];
-        let states = initial_state(&nfa, true);
-        assert_eq!(states, [(1, 1.0), (2, 1.0), (3, 0.0)].into());
+        let states = initial_state(&nfa, true, false);
+        assert_eq!(states, [(counted(2, &[(1, 1)]), 1.0)].into());
 
-        let counts = add_counts(&states, &HashMap::new());
-        assert_eq!(counts, [(1, 1), (2, 1)].into());
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        assert_eq!(states, [(1, 1.0), (2, 1.0)].into());
+        let states = step(states, 'a', &nfa);
+        assert_eq!(states, [(counted(2, &[(1, 2)]), 1.0)].into());
 
-        let counts = add_counts(&states, &counts);
-        assert_eq!(counts, [(1, 2), (2, 2)].into());
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        assert_eq!(states, [(1, 1.0), (2, 1.0), (3, 1.0)].into());
+        let states = step(states, 'a', &nfa);
+        assert_eq!(states, at(&[(3, 1.0)]));
 
-        let counts = add_counts(&states, &counts);
-        let states = step_states(states, &counts, &Kind::Literal('b'), &nfa);
-        assert_eq!(states, [(4, 1.0)].into());
+        let states = step(states, 'b', &nfa);
+        assert_eq!(states, at(&[(4, 1.0)]));
     }
 
     #[test]
@@ -179,23 +522,27 @@ mod test {
             State::literal('b', (Some(4), None)),
             State::terminal(),
         ];
-        let states = initial_state(&nfa, true);
-        assert_eq!(states, [(1, 1.0), (2, 1.0), (3, 0.0)].into());
-
-        let counts = add_counts(&states, &HashMap::new());
-        assert_eq!(counts, [(1, 1), (2, 1)].into());
-
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        assert_eq!(states, [(1, 1.0), (2, 1.0), (3, 0.5)].into());
-
-        let counts = add_counts(&states, &counts);
-        assert_eq!(counts, [(1, 2), (2, 2), (3, 1)].into());
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        assert_eq!(states, [(1, 1.0), (2, 1.0), (3, 0.25)].into());
-
-        let counts = add_counts(&states, &counts);
-        let states = step_states(states, &counts, &Kind::Literal('b'), &nfa);
-        assert_eq!(states, [(4, 0.25)].into());
+        let states = initial_state(&nfa, true, false);
+        assert_eq!(
+            states,
+            [(counted(2, &[(1, 1)]), 1.0), (Thread::at(3), 0.0)].into()
+        );
+
+        // the geometric can not tell counts past 1 apart, they scale p instead
+        let states = step(states, 'a', &nfa);
+        assert_eq!(
+            states,
+            [(counted(2, &[(1, 1)]), 0.5), (Thread::at(3), 0.5)].into()
+        );
+
+        let states = step(states, 'a', &nfa);
+        assert_eq!(
+            states,
+            [(counted(2, &[(1, 1)]), 0.25), (Thread::at(3), 0.25)].into()
+        );
+
+        let states = step(states, 'b', &nfa);
+        assert_eq!(states, at(&[(4, 0.25)]));
     }
 
     #[test]
@@ -211,24 +558,175 @@ mod test {
             State::literal('b', (Some(4), None)),
             State::terminal(),
         ];
-        let states = initial_state(&nfa, true);
-        let counts = add_counts(&states, &HashMap::new());
-        assert_eq!(states, [(1, 1.0), (2, 1.0), (3, 0.0)].into());
-        assert_eq!(counts, [(1, 1), (2, 1)].into());
+        let states = initial_state(&nfa, true, false);
+        assert_eq!(
+            states,
+            [(counted(2, &[(1, 1)]), 1.0), (Thread::at(3), 0.0)].into()
+        );
+
+        let states = step(states, 'a', &nfa);
+        assert_eq!(states, [(counted(2, &[(1, 2)]), 1.0)].into());
+
+        let states = step(states, 'a', &nfa);
+        assert_eq!(
+            states,
+            [(counted(2, &[(1, 2)]), 0.5), (Thread::at(3), 0.5)].into()
+        );
+
+        let states = step(states, 'a', &nfa);
+        assert_eq!(
+            states,
+            [(counted(2, &[(1, 2)]), 0.25), (Thread::at(3), 0.25)].into()
+        );
+
+        let states = step(states, 'b', &nfa);
+        assert_eq!(states, at(&[(4, 0.25)]));
+    }
 
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        let counts = add_counts(&states, &counts);
-        assert_eq!(states, [(1, 1.0), (2, 1.0)].into());
+    #[test]
+    fn test_step_states_overlapping_starts() {
+        // threads entering the quantifier at each offset count on their own,
+        // up to the count the geometric tells apart
+        let nfa = compile("a{2~Geo(0.5)}b").unwrap();
+        let states = initial_state(&nfa, false, false);
+        let states = step_states(states, &Kind::Start, &nfa, Semiring::MaxProduct, false);
+        let states = step(states, 'a', &nfa);
+        let states = step(states, 'a', &nfa);
+
+        let counts: Vec<u64> = states
+            .keys()
+            .filter(|Thread(idx, _)| *idx == 2)
+            .map(|Thread(_, counters)| counters[&1])
+            .sorted()
+            .collect();
+        assert_eq!(counts, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_step_states_bounded_threads() {
+        // unanchored counted quantifiers used to keep a thread per offset
+        let nfa = compile("a{3~Geo(0.5)}").unwrap();
+        let mut states = initial_state(&nfa, false, true);
+        states = step_states(states, &Kind::Start, &nfa, Semiring::MaxProduct, true);
+        for _ in 0..2000 {
+            let token = Kind::Literal('a');
+            states = step_states(states, &token, &nfa, Semiring::MaxProduct, true);
+            assert!(states.len() <= 6, "{} threads", states.len());
+        }
 
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        let counts = add_counts(&states, &counts);
-        assert_eq!(states, [(1, 1.0), (2, 1.0), (3, 0.5)].into());
+        // not memoryless, so counts are told apart, but at most one per offset
+        let nfa = compile("a{3~Pois(2.0)}").unwrap();
+        let mut states = initial_state(&nfa, false, true);
+        states = step_states(states, &Kind::Start, &nfa, Semiring::SumProduct, true);
+        for _ in 0..200 {
+            let token = Kind::Literal('a');
+            states = step_states(states, &token, &nfa, Semiring::SumProduct, true);
+        }
+        assert!(states.len() <= 210, "{} threads", states.len());
+
+        // the path still tells the full count
+        let nfa = compile("^a{3~Geo(0.5)}$").unwrap();
+        let path = best_path(&nfa, &"a".repeat(500)).unwrap();
+        let counts: Vec<u64> = path
+            .steps
+            .iter()
+            .flat_map(|step| step.repetitions.iter().map(|r| r.count))
+            .collect();
+        assert_eq!(counts, vec![500]);
+        assert_relative_eq!(
+            path.log_likelihood,
+            498.0 * 0.5_f64.ln(),
+            max_relative = 1e-9
+        );
+    }
 
-        let states = step_states(states, &counts, &Kind::Literal('a'), &nfa);
-        let counts = add_counts(&states, &counts);
-        assert_eq!(states, [(1, 1.0), (2, 1.0), (3, 0.25)].into());
+    #[test]
+    fn test_match_likelihood_overlapping_starts() {
+        // the global count of the quantifier used to mix up the offsets
+        let nfa = compile("^x*a{2~Geo(0.5)}b$").unwrap();
+        let input = "xaaab".to_string();
+        assert_eq!(match_likelihood(&nfa, &input, false), Some(0.25));
+
+        let nfa = compile("a{2~Geo(0.5)}b").unwrap();
+        assert_eq!(match_likelihood(&nfa, &input, false), Some(0.5));
+        assert_eq!(
+            match_likelihood_with(&nfa, &input, Semiring::SumProduct, false, false),
+            Some(0.25 + 0.5)
+        );
+    }
 
-        let states = step_states(states, &counts, &Kind::Literal('b'), &nfa);
-        assert_eq!(states, [(4, 0.25)].into());
+    #[test]
+    fn test_match_likelihood_overlapping_zipf() {
+        // a run of n a's, with the start of the match anywhere before it
+        let nfa = compile("^x*a{1,3~Zipf(1.0)}y$").unwrap();
+        let normalizer = 1.0 + 1.0 / 2.0 + 1.0 / 3.0;
+        for (input, n) in [("xay", 1.0), ("xxaay", 2.0), ("aaay", 3.0)] {
+            assert_relative_eq!(
+                match_likelihood(&nfa, &input.to_string(), false).unwrap(),
+                (1.0 / n) / normalizer
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_at() {
+        let nfa = compile("ab{1~Geo(0.5)}").unwrap();
+        let text = "xabbyab";
+        // "abb" is less likely, "ab" at 5 is not leftmost
+        let m = find_at(&nfa, text, 0, Semiring::MaxProduct).unwrap();
+        assert_eq!((m.start, m.end), (1, 3));
+        assert_relative_eq!(
+            m.log_likelihood,
+            match_likelihood_with(&nfa, &text.to_string(), Semiring::MaxProduct, true, false)
+                .unwrap()
+        );
+        let m = find_at(&nfa, text, 2, Semiring::SumProduct).unwrap();
+        assert_eq!((m.start, m.end), (5, 7));
+
+        let m = find_at(&nfa, "abab", 1, Semiring::MaxProduct).unwrap();
+        assert_eq!((m.start, m.end), (2, 4));
+        assert_eq!(find_at(&nfa, "abab", 3, Semiring::MaxProduct), None);
+        assert_eq!(find_at(&nfa, "", 0, Semiring::MaxProduct), None);
+    }
+
+    #[test]
+    fn test_find_at_from() {
+        let nfa = compile("a+b").unwrap();
+        // the threads of starts before from are not simulated
+        let m = find_at(&nfa, "aaab", 2, Semiring::MaxProduct).unwrap();
+        assert_eq!((m.start, m.end), (2, 4));
+        // inside a character, the match starts at the next one
+        let m = find_at(&nfa, "éab", 1, Semiring::MaxProduct).unwrap();
+        assert_eq!((m.start, m.end), (2, 4));
+        assert_eq!(find_at(&nfa, "ab", 3, Semiring::MaxProduct), None);
+
+        // only the text from on is simulated, whatever the semiring
+        let nfa = compile("a{1~Geo(0.5)}").unwrap();
+        let text = "a".repeat(2000);
+        let m = find_at(&nfa, &text, 1000, Semiring::MaxProduct).unwrap();
+        assert_eq!((m.start, m.end), (1000, 1001));
+        let m = find_at(&nfa, &text, 1990, Semiring::SumProduct).unwrap();
+        assert_eq!((m.start, m.end), (1990, 1991));
+    }
+
+    #[test]
+    fn test_find_at_zero_likelihood() {
+        // the end of text can not start a{1~Geo}, which needs an a
+        let nfa = compile("a{1~Geo(0.5)}").unwrap();
+        let m = find_at(&nfa, "baaab aab", 6, Semiring::MaxProduct).unwrap();
+        assert_eq!((m.start, m.end), (6, 7));
+        assert_eq!(find_at(&nfa, "baaab aab", 8, Semiring::MaxProduct), None);
+        assert_eq!(find_at(&nfa, "b", 0, Semiring::SumProduct), None);
+    }
+
+    #[test]
+    fn test_find_at_anchored() {
+        let nfa = compile("^ab").unwrap();
+        assert!(find_at(&nfa, "abab", 0, Semiring::MaxProduct).is_some());
+        assert_eq!(find_at(&nfa, "abab", 1, Semiring::MaxProduct), None);
+
+        let nfa = compile("b$").unwrap();
+        let m = find_at(&nfa, "abab", 0, Semiring::MaxProduct).unwrap();
+        assert_eq!((m.start, m.end), (3, 4));
     }
 }
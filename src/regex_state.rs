@@ -1,195 +1,447 @@
-use colored::Colorize;
-
 use crate::{
-    ast::{AstNode, Kind},
-    distribution::Dist,
+    ast::Kind,
+    charclass::member_index,
+    distribution::{Dist, DistLink},
     nfa::State,
-    visualization,
+    semiring::{mul, one, scale, zero, Semiring},
 };
-use itertools::Itertools;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
 pub type Token = Kind;
 
-pub fn initial_state(nfa: &Vec<State>, skip_start: bool) -> HashMap<usize, f64> {
+/// Repetition counts of the quantifiers a thread is looping in, by quantifier state
+pub type Counters = BTreeMap<usize, u64>;
+
+/// Active threads with their probabilities
+pub type States = HashMap<Thread, f64>;
+
+/// Thread of the simulation, a state of the NFA with its own repetition counters
+///
+/// Threads at the same state with different counters are kept apart, so that
+/// paths that entered a quantifier at different offsets do not share a count.
+/// Counts stop where the distribution can no longer tell them apart, see
+/// `Dist::saturation`, so that the number of threads stays bounded.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Thread(pub usize, pub Counters);
+
+impl Thread {
+    /// Thread at state, outside of any counted quantifier
+    pub fn at(state: usize) -> Thread {
+        Thread(state, Counters::new())
+    }
+}
+
+/// Active threads before the first token, with probabilities in linear or log space
+pub fn initial_state(nfa: &[State], skip_start: bool, log: bool) -> States {
     let transitions = evaluate_state(
         Some(0),
         &Kind::Start,
-        1.0,
+        one(log),
+        &Counters::new(),
         nfa,
-        &HashMap::new(),
-        &HashMap::new(),
+        &[],
         // for simpler testing (n need for Kind::Start token everywhere)
         !skip_start,
+        log,
     );
-    return transitions
+    transitions
         .into_iter()
-        .filter_map(|t| match t {
-            Transition(Some(t), p) => Some((t, p)),
-            Transition(None, _) => None,
-        })
-        .collect();
+        .map(|Transition(thread, p, _)| (thread, p))
+        .collect()
 }
 
-pub fn terminal_state_p(states: &HashMap<usize, f64>, nfa: &Vec<State>) -> Option<f64> {
+/// Probability of the threads that reached the terminal state, merged by semiring
+pub fn terminal_state_p(
+    states: &States,
+    nfa: &[State],
+    semiring: Semiring,
+    log: bool,
+) -> Option<f64> {
     // TODO may not be the terminal state
     let idx_terminal = nfa.len() - 1;
-    states.get(&idx_terminal).map(|p| *p)
+    states
+        .iter()
+        .filter(|(Thread(idx, _), _)| *idx == idx_terminal)
+        .map(|(_, p)| *p)
+        .reduce(|a, b| semiring.add(a, b, log))
 }
 
-/// Evaluate the state idx against token, return transitions to next states
+/// Evaluate the state idx against token, return transitions to next threads
+///
+/// The counters are those of the thread being evaluated. Visited are the states
+/// of the epsilon path leading to idx, which is cut where it loops back on itself.
+/// Probabilities p and those of the returned transitions are in log space if log is set.
+#[allow(clippy::too_many_arguments)]
 pub fn evaluate_state(
     idx: Option<usize>,
     token: &Token,
     p: f64,
-    nfa: &Vec<State>,
-    counts: &HashMap<usize, u64>,
-    states: &HashMap<usize, f64>, // for base p for quantifier
+    counters: &Counters,
+    nfa: &[State],
+    visited: &[usize],
     is_epsilon: bool,
+    log: bool,
 ) -> Vec<Transition> {
     let idx = if let Some(idx) = idx {
         idx
     } else {
         return vec![];
     };
+    if is_epsilon && visited.contains(&idx) {
+        return vec![];
+    }
+    let thread = || Thread(idx, counters.clone());
+    let path = || [visited, &[idx]].concat();
 
     if let Some(state) = nfa.get(idx) {
         match state.kind {
             Kind::Terminal => {
-                return vec![Transition(Some(idx), p)];
+                return vec![Transition(thread(), p, vec![])];
             }
             Kind::Start => {
                 if is_epsilon {
-                    return vec![Transition(Some(idx), 1.0)];
+                    return vec![Transition(thread(), one(log), vec![])];
                 }
                 return [
                     // Always keep start in states
-                    vec![Transition(Some(idx), 1.0)],
+                    vec![Transition(thread(), one(log), vec![])],
                     // Add states along out arrows
-                    evaluate_state_outs(state.outs, token, p, nfa, counts, states, true),
+                    evaluate_state_outs(state.outs, token, p, counters, nfa, &path(), true, log),
                 ]
                 .concat();
             }
             Kind::AnchorStart => {
                 if is_epsilon {
-                    return vec![Transition(Some(idx), 1.0)];
+                    return vec![Transition(thread(), one(log), vec![])];
                 }
                 if *token == Kind::Start {
                     // Add state along out arrow
-                    return evaluate_state(state.outs.0, token, p, nfa, counts, states, true);
+                    return evaluate_state(
+                        state.outs.0,
+                        token,
+                        p,
+                        counters,
+                        nfa,
+                        &path(),
+                        true,
+                        log,
+                    );
                 }
             }
             Kind::AnchorEnd => {
                 if is_epsilon {
-                    return vec![Transition(Some(idx), p)];
+                    return vec![Transition(thread(), p, vec![])];
                 }
                 if *token == Kind::Terminal {
                     // Add terminal state
-                    return vec![Transition(state.outs.0, p)];
+                    return evaluate_state(state.outs.0, token, p, counters, nfa, &[], true, log);
                 }
             }
             Kind::Split => {
-                return evaluate_state_outs(state.outs, token, p, nfa, counts, states, true);
+                let (p_left, p_right) = match &state.dist {
+                    Some(dist) => dist.pmf_split(log),
+                    None => (one(log), one(log)),
+                };
+                let (left, right) = state.outs;
+                let path = path();
+                // Only weighted branches are worth explaining, the choice of others costs nothing
+                let branch = |is_left: bool| {
+                    move |Transition(thread, p, mut passed): Transition| {
+                        if state.dist.is_some() {
+                            passed.insert(0, Passed::Branch(idx, is_left));
+                        }
+                        Transition(thread, p, passed)
+                    }
+                };
+                return [
+                    evaluate_state(
+                        left,
+                        token,
+                        mul(p, p_left, log),
+                        counters,
+                        nfa,
+                        &path,
+                        true,
+                        log,
+                    )
+                    .into_iter()
+                    .map(branch(true))
+                    .collect::<Vec<_>>(),
+                    evaluate_state(
+                        right,
+                        token,
+                        mul(p, p_right, log),
+                        counters,
+                        nfa,
+                        &path,
+                        true,
+                        log,
+                    )
+                    .into_iter()
+                    .map(branch(false))
+                    .collect(),
+                ]
+                .concat();
             }
-            Kind::Quantifier(_) | Kind::ExactQuantifier(_) => {
-                // NOTE: !
+            Kind::Quantifier(_) | Kind::ExactQuantifier(_) | Kind::RangeQuantifier(_, _) => {
                 if !is_epsilon {
-                    // Direct evaluation is no-op, since state used for counting only
+                    // Quantifiers are passed through, they never consume a token
                     return vec![];
                 }
 
-                // quantifier now visited current n times
-                let n = *counts.get(&idx).unwrap_or(&0);
+                // quantified expression repeated n times so far on this thread
+                let n = *counters.get(&idx).unwrap_or(&0);
 
-                let pb = *states.get(&idx).unwrap_or(&p);
                 let (_, p1) = match &state.dist {
-                    Some(dist) => dist.pmf_link(token, Some(n), &state.kind, false, false),
-                    None => (1., 1.),
+                    Some(dist) => dist.pmf_link(token, Some(n), &state.kind, false, log),
+                    None => (one(log), one(log)),
+                };
+
+                // Bounded repetition neither loops past nor exits outside its bounds
+                let (is_loop, is_exit) = match state.kind {
+                    Kind::Quantifier('+') => (true, n >= 1),
+                    Kind::ExactQuantifier(n_max) if is_exactly(&state.dist) => {
+                        (n < n_max, n == n_max)
+                    }
+                    Kind::RangeQuantifier(n_min, n_max) => (n < n_max, n >= n_min && n <= n_max),
+                    _ => (true, true),
+                };
+
+                // Only distributions and bounds depend on the count, + only on the first
+                let is_counted = state.dist.is_some() && state.kind != Kind::Quantifier('?');
+                // ? never comes back to count, so entering it takes the mass of one repetition
+                let is_optional = state.dist.is_some() && state.kind == Kind::Quantifier('?');
+                let p_enter = match (&state.dist, is_optional) {
+                    (Some(dist), true) => dist.pmf_link(token, Some(1), &state.kind, false, log).1,
+                    _ => one(log),
+                };
+                let n_next = match (is_counted, &state.kind) {
+                    (true, _) => Some(n + 1),
+                    (false, Kind::Quantifier('+')) => Some(1),
+                    (false, _) => None,
+                };
+                // Past the count the distribution tells apart, repeating scales p instead
+                let saturated = match (&state.dist, n_next) {
+                    (Some(DistLink::Counted(dist)), Some(n_next)) => dist.saturation(n_next),
+                    _ => None,
+                };
+                let (n_next, p_enter) = match saturated {
+                    Some((n_saturated, factor)) => {
+                        (Some(n_saturated), mul(p_enter, scale(factor, log), log))
+                    }
+                    None => (n_next, p_enter),
                 };
 
                 return [
-                    // Always add quantifier state for counting & storing base p
-                    vec![Transition(Some(idx), pb)],
-                    // Re-add outs.0 (quantified) with base p, since quantifier p is applied only to next state
-                    evaluate_state(state.outs.0, token, p, nfa, counts, states, true),
-                    evaluate_state(state.outs.1, token, p * p1, nfa, counts, states, true),
+                    // Enter the quantified expression, counting one more repetition
+                    match is_loop {
+                        true => {
+                            let mut looped = counters.clone();
+                            if let Some(n_next) = n_next {
+                                looped.insert(idx, n_next);
+                            }
+                            evaluate_state(
+                                state.outs.0,
+                                token,
+                                mul(p, p_enter, log),
+                                &looped,
+                                nfa,
+                                &path(),
+                                true,
+                                log,
+                            )
+                            .into_iter()
+                            .map(|Transition(thread, p, mut passed)| {
+                                if is_optional {
+                                    passed.insert(0, Passed::Exit(idx, 1));
+                                }
+                                if saturated.is_some() {
+                                    passed.insert(0, Passed::Repeat(idx));
+                                }
+                                Transition(thread, p, passed)
+                            })
+                            .collect()
+                        }
+                        false => vec![],
+                    },
+                    // Leave the quantified expression, resetting its count
+                    match is_exit {
+                        true => {
+                            let mut exited = counters.clone();
+                            exited.remove(&idx);
+                            evaluate_state(
+                                state.outs.1,
+                                token,
+                                mul(p, p1, log),
+                                &exited,
+                                nfa,
+                                &path(),
+                                true,
+                                log,
+                            )
+                            .into_iter()
+                            // Record the repetition count the path leaves the quantifier with
+                            .map(|Transition(thread, p, mut passed)| {
+                                if is_counted || is_optional {
+                                    passed.insert(0, Passed::Exit(idx, n));
+                                }
+                                Transition(thread, p, passed)
+                            })
+                            .collect()
+                        }
+                        false => vec![],
+                    },
                 ]
                 .concat();
             }
+            Kind::GroupOpen(_, _) | Kind::GroupClose(_) => {
+                // Group boundaries are passed through, remembering where the path was
+                return evaluate_state(state.outs.0, token, p, counters, nfa, &path(), true, log)
+                    .into_iter()
+                    .map(|Transition(thread, p_next, mut passed)| {
+                        passed.insert(0, Passed::Group(idx, p));
+                        Transition(thread, p_next, passed)
+                    })
+                    .collect();
+            }
             Kind::Dot => {
                 if is_epsilon {
-                    return vec![Transition(Some(idx), p)];
+                    return vec![Transition(thread(), p, vec![])];
                 }
 
-                return evaluate_state_outs(state.outs, token, p, nfa, counts, states, true);
+                // Any character but a newline, and not the start or end of input
+                if let Kind::Literal(c) = token {
+                    if *c == '\n' {
+                        return vec![];
+                    }
+                    let (_, p1) = match &state.dist {
+                        // `.` is the negation of the empty class
+                        Some(dist) => dist.pmf_link(token, None, &state.kind, true, log),
+                        None => (one(log), one(log)),
+                    };
+                    return evaluate_state(
+                        state.outs.0,
+                        token,
+                        mul(p, p1, log),
+                        counters,
+                        nfa,
+                        &[],
+                        true,
+                        log,
+                    );
+                }
             }
             Kind::Literal(match_c) => {
                 if is_epsilon {
-                    return vec![Transition(Some(idx), p)];
+                    return vec![Transition(thread(), p, vec![])];
                 }
 
                 if let Kind::Literal(c) = token {
                     if *c == match_c {
-                        return evaluate_state(state.outs.0, token, p, nfa, counts, states, true);
+                        return evaluate_state(
+                            state.outs.0,
+                            token,
+                            p,
+                            counters,
+                            nfa,
+                            &[],
+                            true,
+                            log,
+                        );
                     }
                 }
             }
-            Kind::Class(is_negate, ref match_c) => {
+            Kind::Class(is_negate, ref match_c) | Kind::FoldedClass(is_negate, ref match_c) => {
                 if is_epsilon {
-                    return vec![Transition(Some(idx), p)];
+                    return vec![Transition(thread(), p, vec![])];
                 }
 
                 if let Kind::Literal(c) = token {
-                    let idx = match match_c.iter().position(|&r| r == *c) {
-                        Some(i) => Some(i as u64),
-                        None => None,
-                    };
+                    let is_folded = matches!(state.kind, Kind::FoldedClass(_, _));
+                    let idx = match &state.dist {
+                        // members are in code point order without a distribution over them
+                        None | Some(DistLink::Indexed(Dist::Background(_))) => {
+                            match_c.binary_search(c).ok()
+                        }
+                        Some(_) => member_index(match_c, *c, is_folded),
+                    }
+                    .map(|i| i as u64);
                     let (_, p1) = match &state.dist {
-                        Some(dist) => dist.pmf_link(token, idx, &state.kind, is_negate, false),
+                        Some(dist) => dist.pmf_link(token, idx, &state.kind, is_negate, log),
                         None => match (idx, is_negate) {
-                            (None, false) => (1., 0.),
-                            (None, true) => (1., 1.),
-                            (Some(_), false) => (1., 1.),
-                            (Some(_), true) => (1., 0.),
+                            (None, false) | (Some(_), true) => (one(log), zero(log)),
+                            (None, true) | (Some(_), false) => (one(log), one(log)),
                         },
                     };
 
-                    return evaluate_state(state.outs.0, token, p * p1, nfa, counts, states, true);
+                    return evaluate_state(
+                        state.outs.0,
+                        token,
+                        mul(p, p1, log),
+                        counters,
+                        nfa,
+                        &[],
+                        true,
+                        log,
+                    );
                 }
                 return vec![];
             }
             _ => {}
         }
     }
-    return vec![];
+    vec![]
+}
+
+/// True for the default distribution of an exact quantifier, which has no mass past it
+fn is_exactly(dist: &Option<DistLink>) -> bool {
+    matches!(dist, Some(DistLink::Counted(Dist::ExactlyTimes(_))))
 }
 
 /// Helper for evaluating multiple states at once
+#[allow(clippy::too_many_arguments)]
 fn evaluate_state_outs(
     outs: (Option<usize>, Option<usize>),
     token: &Token,
     p: f64,
-    nfa: &Vec<State>,
-    counts: &HashMap<usize, u64>,
-    states: &HashMap<usize, f64>,
+    counters: &Counters,
+    nfa: &[State],
+    visited: &[usize],
     is_epsilon: bool,
+    log: bool,
 ) -> Vec<Transition> {
     [
-        evaluate_state(outs.0, token, p, nfa, counts, states, is_epsilon),
-        evaluate_state(outs.1, token, p, nfa, counts, states, is_epsilon),
+        evaluate_state(outs.0, token, p, counters, nfa, visited, is_epsilon, log),
+        evaluate_state(outs.1, token, p, counters, nfa, visited, is_epsilon, log),
     ]
     .concat()
 }
 
-// struct StateContext(());
+/// Transition to a next thread with its probability
+///
+/// Also lists the states the transition passes along the way that matter
+/// for explaining the path, in the order it passes them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition(pub Thread, pub f64, pub Vec<Passed>);
+
+/// State passed by a transition between two threads
 #[derive(Debug, Clone, PartialEq)]
-pub struct Transition(pub Option<usize>, pub f64);
+pub enum Passed {
+    /// Exit of the quantifier at the state, after the count of repetitions
+    Exit(usize, u64),
+    /// Boundary of the group at the state, with the probability of the path there
+    Group(usize, f64),
+    /// Repetition of the quantifier at the state past the count its thread keeps
+    Repeat(usize),
+    /// Weighted split at the state, taking its left branch or the right one
+    Branch(usize, bool),
+}
 
 /// Newtype for vector of input tokens
 pub struct Tokens(Vec<Kind>);
 
 impl Tokens {
-    pub fn as_vec(self) -> Vec<Token> {
-        return self.0;
+    pub fn into_vec(self) -> Vec<Token> {
+        self.0
     }
 }
 
@@ -208,18 +460,32 @@ impl From<String> for Tokens {
 
 #[cfg(test)]
 mod test {
-    use crate::distribution::DistLink;
-
     use super::*;
+    use crate::compile;
+
+    /// Evaluate a single state for token, with no counters
+    fn evaluate(idx: usize, token: char, nfa: &[State]) -> Vec<Transition> {
+        let token = Kind::Literal(token);
+        evaluate_state(
+            Some(idx),
+            &token,
+            1.0,
+            &Counters::new(),
+            nfa,
+            &[],
+            false,
+            false,
+        )
+    }
 
     #[test]
     fn test_initial_state_start() {
         let nfa = vec![State::start(Some(1)), State::literal('a', (Some(2), None))];
-        let states = initial_state(&nfa, false);
-        assert_eq!(states, [(0, 1.0)].into());
+        let states = initial_state(&nfa, false, false);
+        assert_eq!(states, [(Thread::at(0), 1.0)].into());
 
-        let states = initial_state(&nfa, true);
-        assert_eq!(states, [(0, 1.0), (1, 1.0)].into());
+        let states = initial_state(&nfa, true, false);
+        assert_eq!(states, [(Thread::at(0), 1.0), (Thread::at(1), 1.0)].into());
     }
 
     #[test]
@@ -228,11 +494,11 @@ mod test {
             State::anchor_start(Some(1)),
             State::literal('a', (Some(2), None)),
         ];
-        let states = initial_state(&nfa, false);
-        assert_eq!(states, [(0, 1.0)].into());
+        let states = initial_state(&nfa, false, false);
+        assert_eq!(states, [(Thread::at(0), 1.0)].into());
 
-        let states = initial_state(&nfa, true);
-        assert_eq!(states, [(1, 1.0)].into());
+        let states = initial_state(&nfa, true, false);
+        assert_eq!(states, [(Thread::at(1), 1.0)].into());
     }
 
     #[test]
@@ -243,29 +509,11 @@ mod test {
             State::literal('b', (Some(3), None)),
             State::terminal(),
         ];
-        let counts: HashMap<usize, u64> = HashMap::new();
-        let states: HashMap<usize, f64> = HashMap::new();
 
-        let transitions = evaluate_state(
-            Some(1),
-            &Kind::Literal('a'),
-            1.0,
-            &nfa,
-            &counts,
-            &states,
-            false,
-        );
-        assert_eq!(transitions, vec![Transition(Some(2), 1.0)]);
+        let transitions = evaluate(1, 'a', &nfa);
+        assert_eq!(transitions, vec![Transition(Thread::at(2), 1.0, vec![])]);
 
-        let transitions = evaluate_state(
-            Some(1),
-            &Kind::Literal('b'),
-            1.0,
-            &nfa,
-            &counts,
-            &states,
-            false,
-        );
+        let transitions = evaluate(1, 'b', &nfa);
         assert_eq!(transitions, vec![]);
     }
 
@@ -277,27 +525,29 @@ mod test {
             State::dot((Some(3), None)),
             State::terminal(),
         ];
-        let counts: HashMap<usize, u64> = HashMap::new();
-        let states: HashMap<usize, f64> = HashMap::new();
 
-        let transitions = evaluate_state(
-            Some(1),
-            &Kind::Literal('a'),
-            1.0,
-            &nfa,
-            &counts,
-            &states,
-            false,
-        );
-        assert_eq!(transitions, vec![Transition(Some(2), 1.0)]);
+        let transitions = evaluate(1, 'a', &nfa);
+        assert_eq!(transitions, vec![Transition(Thread::at(2), 1.0, vec![])]);
+
+        let transitions = evaluate(1, 'b', &nfa);
+        assert_eq!(transitions, vec![]);
+
+        let transitions = evaluate(2, 'b', &nfa);
+        assert_eq!(transitions, vec![Transition(Thread::at(3), 1.0, vec![])]);
+    }
 
+    #[test]
+    fn test_evaluate_state_dot_end() {
+        // the end of input is not a character
+        let nfa = vec![State::dot((Some(1), None)), State::terminal()];
         let transitions = evaluate_state(
-            Some(1),
-            &Kind::Literal('b'),
+            Some(0),
+            &Kind::Terminal,
             1.0,
+            &Counters::new(),
             &nfa,
-            &counts,
-            &states,
+            &[],
+            false,
             false,
         );
         assert_eq!(transitions, vec![]);
@@ -316,28 +566,101 @@ mod test {
             State::literal('b', (Some(4), None)),
             State::terminal(),
         ];
-        let counts: HashMap<usize, u64> = HashMap::new();
-        let states: HashMap<usize, f64> = HashMap::new();
 
+        let transitions = evaluate(2, 'a', &nfa);
+        assert_eq!(
+            transitions,
+            vec![
+                // Loop, with the repetition counted on the thread
+                Transition(Thread(2, [(1, 1)].into()), 1.0, vec![]),
+                // Exit, with no mass before two repetitions
+                Transition(Thread::at(3), 0.0, vec![Passed::Exit(1, 0)]),
+            ]
+        );
+
+        let counters: Counters = [(1, 2)].into();
         let transitions = evaluate_state(
             Some(2),
             &Kind::Literal('a'),
             1.0,
+            &counters,
             &nfa,
-            &counts,
-            &states,
+            &[],
+            false,
             false,
         );
         assert_eq!(
             transitions,
             vec![
-                Transition(Some(1), 1.0), // Store p_entry
-                Transition(Some(2), 1.0),
-                Transition(Some(3), 0.0)
+                // Loop past the count the geometric tells apart
+                Transition(Thread(2, [(1, 2)].into()), 0.5, vec![Passed::Repeat(1)]),
+                Transition(Thread::at(3), 0.5, vec![Passed::Exit(1, 2)]),
             ]
         );
+    }
+
+    #[test]
+    fn test_evaluate_state_exact_quantifier_bounded() {
+        let nfa = compile("^a{2}b").unwrap();
+
+        // no loop past the exact count
+        let counters: Counters = [(1, 2)].into();
+        let transitions = evaluate_state(
+            Some(1),
+            &Kind::Literal('a'),
+            1.0,
+            &counters,
+            &nfa,
+            &[],
+            true,
+            false,
+        );
+        assert_eq!(
+            transitions,
+            vec![Transition(Thread::at(3), 1.0, vec![Passed::Exit(1, 2)])]
+        );
+    }
+
+    #[test]
+    fn test_evaluate_state_plus() {
+        let nfa = compile("a+b").unwrap();
+        assert_eq!(nfa[1].kind, Kind::Quantifier('+'));
+
+        // + is not left before the first repetition
+        let transitions = evaluate_state(
+            Some(1),
+            &Kind::Literal('x'),
+            1.0,
+            &Counters::new(),
+            &nfa,
+            &[],
+            true,
+            false,
+        );
+        assert_eq!(
+            transitions,
+            vec![Transition(Thread(2, [(1, 1)].into()), 1.0, vec![])]
+        );
+    }
 
-        // TODO more steps
+    #[test]
+    fn test_evaluate_state_epsilon_cycle() {
+        // the inner star can exit into the outer star without consuming
+        let nfa = compile("^(?:a*)*b$").unwrap();
+        let transitions = evaluate_state(
+            Some(1),
+            &Kind::Literal('a'),
+            1.0,
+            &Counters::new(),
+            &nfa,
+            &[],
+            true,
+            false,
+        );
+        let mut states: Vec<usize> = transitions.iter().map(|t| t.0 .0).collect();
+        states.sort();
+        states.dedup();
+        assert_eq!(states, vec![3, 4]);
     }
 
     #[test]
@@ -351,22 +674,41 @@ mod test {
             ),
             State::terminal(),
         ];
-        let counts: HashMap<usize, u64> = HashMap::new();
-        let states: HashMap<usize, f64> = HashMap::new();
 
-        let transitions = evaluate_state(
-            Some(1),
-            &Kind::Literal('a'),
-            1.0,
-            &nfa,
-            &counts,
-            &states,
-            false,
-        );
+        let transitions = evaluate(1, 'a', &nfa);
         assert_eq!(
             transitions,
             // NOTE: The character class is not a quantifier
-            vec![Transition(Some(2), 0.5),]
+            vec![Transition(Thread::at(2), 0.5, vec![])]
+        );
+    }
+
+    #[test]
+    fn test_terminal_state_p() {
+        let nfa = vec![State::start(Some(1)), State::terminal()];
+        let states: States = [
+            (Thread::at(0), 1.0),
+            (Thread::at(1), 0.25),
+            (Thread(1, [(5, 1)].into()), 0.5),
+        ]
+        .into();
+
+        assert_eq!(
+            terminal_state_p(&states, &nfa, Semiring::MaxProduct, false),
+            Some(0.5)
+        );
+        assert_eq!(
+            terminal_state_p(&states, &nfa, Semiring::SumProduct, false),
+            Some(0.75)
+        );
+        assert_eq!(
+            terminal_state_p(
+                &[(Thread::at(0), 1.0)].into(),
+                &nfa,
+                Semiring::MaxProduct,
+                false
+            ),
+            None
         );
     }
 }
@@ -0,0 +1,516 @@
+use crate::{
+    ast::Kind,
+    distribution::{zeta_normalizer, Dist, DistLink},
+    nfa::State,
+};
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+use statrs::distribution::{Binomial, Gamma, Geometric, Laplace, Normal, Poisson};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// Characters drawn for `.` and for characters outside of a class
+const ALPHABET: RangeInclusive<char> = ' '..='~';
+
+/// Draws that may fall outside of the bounds, before drawing within them by the cdf
+const N_ATTEMPTS: usize = 64;
+
+/// Draw a random string from the NFA, walking it from start to terminal
+///
+/// Quantifiers repeat a number of times drawn from their distribution, classes
+/// emit a character drawn from theirs and weighted alternations take a branch
+/// drawn from theirs. Choices without a distribution
+/// are uniform, i.e. the branches of an alternation, the members of a class and
+/// printable ASCII for `.`, unless a background model is applied. Plain `*`
+/// and `+` repeat a geometric number of times.
+pub fn sample<R: Rng + ?Sized>(nfa: &[State], rng: &mut R) -> String {
+    let mut output = String::new();
+    // Repetitions left, for the quantifiers the walk is inside of
+    let mut remaining: HashMap<usize, u64> = HashMap::new();
+    let mut idx = Some(0);
+
+    while let Some(i) = idx {
+        let state = &nfa[i];
+        idx = match state.kind {
+            Kind::Terminal => None,
+            Kind::Literal(c) => {
+                output.push(c);
+                state.outs.0
+            }
+            Kind::Dot => {
+                output.push(draw_class(true, &['\n'], &state.dist, rng));
+                state.outs.0
+            }
+            Kind::Class(is_negate, ref chars) | Kind::FoldedClass(is_negate, ref chars) => {
+                output.push(draw_class(is_negate, chars, &state.dist, rng));
+                state.outs.0
+            }
+            Kind::Split => match rng.gen_bool(p_left_branch(&state.dist)) {
+                true => state.outs.0,
+                false => state.outs.1,
+            },
+            // ? never comes back to count down, so it is drawn on every visit
+            Kind::Quantifier('?') => match draw_count(&state.kind, &state.dist, rng) {
+                0 => state.outs.1,
+                _ => state.outs.0,
+            },
+            Kind::Quantifier(_) | Kind::ExactQuantifier(_) | Kind::RangeQuantifier(_, _) => {
+                let n = remaining
+                    .entry(i)
+                    .or_insert_with(|| draw_count(&state.kind, &state.dist, rng));
+                match *n {
+                    0 => {
+                        remaining.remove(&i);
+                        state.outs.1
+                    }
+                    _ => {
+                        *n -= 1;
+                        state.outs.0
+                    }
+                }
+            }
+            _ => state.outs.0,
+        };
+    }
+    output
+}
+
+/// Probability that a split takes its left branch
+fn p_left_branch(dist: &Option<DistLink>) -> f64 {
+    match dist {
+        // The left branch against the rest of the chain
+        Some(DistLink::Indexed(Dist::Categorical(prob_mass))) => {
+            let total: f64 = prob_mass[1..].iter().sum();
+            match total > 0.0 {
+                true => prob_mass[1] / total,
+                false => 0.5,
+            }
+        }
+        _ => 0.5,
+    }
+}
+
+/// Number of repetitions of a quantifier
+fn draw_count<R: Rng + ?Sized>(kind: &Kind, dist: &Option<DistLink>, rng: &mut R) -> u64 {
+    match (kind, dist) {
+        (_, Some(DistLink::Counted(dist))) => draw(dist, rng),
+        (Kind::Quantifier('+'), _) => 1 + draw_geometric(0.5, rng),
+        (Kind::Quantifier('?'), _) => rng.gen_bool(0.5) as u64,
+        (Kind::ExactQuantifier(n), _) => *n,
+        (Kind::RangeQuantifier(n_min, n_max), _) => draw(&Dist::Constant(*n_min, *n_max, 1.0), rng),
+        _ => draw_geometric(0.5, rng),
+    }
+}
+
+/// Value drawn from the distribution, within its bounds
+fn draw<R: Rng + ?Sized>(dist: &Dist, rng: &mut R) -> u64 {
+    match *dist {
+        Dist::ExactlyTimes(n) => n,
+        Dist::Constant(n_min, u64::MAX, _) => n_min + draw_geometric(0.5, rng),
+        Dist::Constant(n_min, n_max, _) => rng.gen_range(n_min..=n_max),
+        Dist::PGeometric(n_min, n_max, p) => {
+            draw_within(dist, n_min, n_max, rng, |rng| draw_geometric(p, rng))
+        }
+        Dist::PBinomial(n_min, n_max, p) => match Binomial::new(p, n_max - n_min) {
+            Ok(binomial) => n_min + binomial.sample(rng) as u64,
+            Err(_) => n_min,
+        },
+        Dist::PBernoulli(n_min, n_max, p) if n_min < n_max => n_min + rng.gen_bool(p) as u64,
+        Dist::PBernoulli(n_min, _, _) => n_min,
+        Dist::PPoisson(n_min, n_max, lambda) => match Poisson::new(lambda) {
+            Ok(poisson) => draw_within(dist, n_min, n_max, rng, |rng| poisson.sample(rng) as u64),
+            Err(_) => n_min,
+        },
+        Dist::PNegBinomial(n_min, n_max, r, p) => draw_within(dist, n_min, n_max, rng, |rng| {
+            draw_negative_binomial(r, p, rng)
+        }),
+        Dist::PNormal(n_min, n_max, mu, sigma) => match Normal::new(mu, sigma) {
+            Ok(normal) => draw_rounded(dist, n_min, n_max, rng, |rng| normal.sample(rng)),
+            Err(_) => n_min,
+        },
+        Dist::PLaplace(n_min, n_max, mu, b) => match Laplace::new(mu, b) {
+            Ok(laplace) => draw_rounded(dist, n_min, n_max, rng, |rng| laplace.sample(rng)),
+            Err(_) => n_min,
+        },
+        Dist::PZipf(n_min, n_max, s) => draw_zipf(u64::max(1, n_min), n_max, s, rng),
+        Dist::PZeta(n_min, n_max, s) => draw_zeta(n_min, n_max, s, rng),
+        Dist::Categorical(ref prob_mass) => match WeightedIndex::new(prob_mass) {
+            Ok(index) => index.sample(rng) as u64,
+            Err(_) => 0,
+        },
+        Dist::Mixture(ref components) => draw(draw_component(components, rng), rng),
+        Dist::Histogram(ref histogram) => match WeightedIndex::new(histogram.values()) {
+            Ok(index) => *histogram.keys().nth(index.sample(rng)).unwrap(),
+            Err(_) => 0,
+        },
+        // Only characters are drawn from a background
+        Dist::Background(_) => 0,
+    }
+}
+
+/// Component of a mixture, drawn by weight
+fn draw_component<'d, R: Rng + ?Sized>(components: &'d [(f64, Dist)], rng: &mut R) -> &'d Dist {
+    match WeightedIndex::new(components.iter().map(|(w, _)| *w)) {
+        Ok(index) => &components[index.sample(rng)].1,
+        Err(_) => &components[0].1,
+    }
+}
+
+/// Draw offset from n_min, again until it is at most n_max, see draw_truncated
+fn draw_within<R, F>(dist: &Dist, n_min: u64, n_max: u64, rng: &mut R, mut draw: F) -> u64
+where
+    R: Rng + ?Sized,
+    F: FnMut(&mut R) -> u64,
+{
+    for _ in 0..N_ATTEMPTS {
+        let n = n_min.saturating_add(draw(rng));
+        if n <= n_max {
+            return n;
+        }
+    }
+    draw_truncated(dist, n_min, n_max, rng).unwrap_or(n_max)
+}
+
+/// Count in n_min..=n_max drawn by inverting the cdf of the distribution within them
+///
+/// Slower than drawing from the whole distribution, but it does not depend on how
+/// little of the mass falls within the bounds. None if the mass within them is
+/// too small to tell apart from zero, when it piles up on the bound nearest the rest.
+fn draw_truncated<R: Rng + ?Sized>(
+    dist: &Dist,
+    n_min: u64,
+    n_max: u64,
+    rng: &mut R,
+) -> Option<u64> {
+    let u = rng.gen::<f64>();
+    let mut mass = 0.0;
+    let mut n_last = None;
+    for n in n_min..=n_max {
+        let p = dist.evaluate(n, false).1;
+        mass += p;
+        if mass > u {
+            return Some(n);
+        }
+        match p > 0.0 {
+            true => n_last = Some(n),
+            // Past the mass, which sums to u or less by rounding, or not a number
+            false if mass > 0.0 || p.is_nan() => break,
+            false => {}
+        }
+    }
+    n_last
+}
+
+/// Draw rounded to the nearest integer, again until it is in n_min..=n_max, see draw_truncated
+fn draw_rounded<R, F>(dist: &Dist, n_min: u64, n_max: u64, rng: &mut R, mut draw: F) -> u64
+where
+    R: Rng + ?Sized,
+    F: FnMut(&mut R) -> f64,
+{
+    let mut x = 0.0;
+    for _ in 0..N_ATTEMPTS {
+        x = draw(rng).round();
+        if x >= n_min as f64 && x <= n_max as f64 {
+            return x as u64;
+        }
+    }
+    let nearest = x.clamp(n_min as f64, n_max as f64) as u64;
+    draw_truncated(dist, n_min, n_max, rng).unwrap_or(nearest)
+}
+
+/// Number of failures before the first success
+fn draw_geometric<R: Rng + ?Sized>(p: f64, rng: &mut R) -> u64 {
+    match Geometric::new(p) {
+        Ok(geometric) => geometric.sample(rng) as u64 - 1,
+        Err(_) => 0,
+    }
+}
+
+/// Number of failures before the r-th success, as a Poisson with a gamma distributed rate
+// statrs draws from a rate of mean r p / (1 - p) rather than r (1 - p) / p
+fn draw_negative_binomial<R: Rng + ?Sized>(r: f64, p: f64, rng: &mut R) -> u64 {
+    let lambda = match Gamma::new(r, p / (1.0 - p)) {
+        Ok(gamma) => gamma.sample(rng),
+        Err(_) => return 0,
+    };
+    match Poisson::new(lambda) {
+        Ok(poisson) => poisson.sample(rng) as u64,
+        Err(_) => 0,
+    }
+}
+
+/// Rank in n_min..=n_max, with mass proportional to rank^-s
+fn draw_zipf<R: Rng + ?Sized>(n_min: u64, n_max: u64, s: f64, rng: &mut R) -> u64 {
+    let weight = |rank: u64| 1.0 / (rank as f64).powf(s);
+    let total: f64 = (n_min..=n_max).map(weight).sum();
+    let mut u = rng.gen::<f64>() * total;
+    for rank in n_min..n_max {
+        u -= weight(rank);
+        if u < 0.0 {
+            return rank;
+        }
+    }
+    n_max
+}
+
+/// Rank from n_min on with mass proportional to rank^-s, by inverting the tail mass
+///
+/// The tail mass past a rank is a Hurwitz zeta function, so the rank is found by
+/// doubling and then bisecting rather than by walking the ranks one by one.
+fn draw_zeta<R: Rng + ?Sized>(n_min: u64, n_max: u64, s: f64, rng: &mut R) -> u64 {
+    // Mass of n_min..=n_max past rank, relative to all of it
+    let normalizer = zeta_normalizer(s, n_min, n_max);
+    let tail = |rank: u64| zeta_normalizer(s, rank, n_max) / normalizer;
+    // In (0, 1], as no rank has a tail mass of zero
+    let u = 1.0 - rng.gen::<f64>();
+    // Largest rank whose tail still holds u, i.e. tail(rank) >= u > tail(rank + 1)
+    let (mut low, mut high) = (n_min, n_max);
+    let mut step = 1u64;
+    while low < high {
+        let probe = u64::min(high, low.saturating_add(step));
+        if tail(probe) < u {
+            high = probe - 1;
+            break;
+        }
+        low = probe;
+        step = step.saturating_mul(2);
+    }
+    while low < high {
+        let mid = low + (high - low).div_ceil(2);
+        match tail(mid) >= u {
+            true => low = mid,
+            false => high = mid - 1,
+        }
+    }
+    low
+}
+
+/// Character emitted by a class, drawn from its distribution over members
+fn draw_class<R: Rng + ?Sized>(
+    is_negate: bool,
+    chars: &[char],
+    dist: &Option<DistLink>,
+    rng: &mut R,
+) -> char {
+    let uniform = |rng: &mut R| match (is_negate, chars.is_empty()) {
+        (false, false) => chars[rng.gen_range(0..chars.len())],
+        _ => draw_outside(chars, rng),
+    };
+    let dist = match dist {
+        Some(DistLink::Indexed(dist)) => dist,
+        _ => return uniform(rng),
+    };
+    match dist {
+        Dist::Mixture(components) => {
+            let component = draw_component(components, rng).clone().index();
+            draw_class(is_negate, chars, &Some(component), rng)
+        }
+        Dist::Background(background) => match background.draw(chars, rng) {
+            Some(c) => c,
+            None => draw_outside(chars, rng),
+        },
+        // Remainder first, for characters outside of the class
+        Dist::Categorical(_) => match draw(dist, rng) as usize {
+            0 => draw_outside(chars, rng),
+            i => chars[i - 1],
+        },
+        // Ranks start at 1
+        Dist::PZipf(_, _, _) => chars[draw(dist, rng) as usize - 1],
+        Dist::PGeometric(_, _, _)
+        | Dist::PBinomial(_, _, _)
+        | Dist::PBernoulli(_, _, _)
+        | Dist::PPoisson(_, _, _)
+        | Dist::PNegBinomial(_, _, _, _)
+        | Dist::PNormal(_, _, _, _)
+        | Dist::PLaplace(_, _, _, _) => {
+            // Mass past the last member has no character, so draw again
+            for _ in 0..N_ATTEMPTS {
+                let i = draw(dist, rng) as usize;
+                if i < chars.len() {
+                    return chars[i];
+                }
+            }
+            let masses = (0..chars.len()).map(|i| dist.evaluate(i as u64, false).1);
+            match WeightedIndex::new(masses) {
+                Ok(index) => chars[index.sample(rng)],
+                Err(_) => uniform(rng),
+            }
+        }
+        _ => uniform(rng),
+    }
+}
+
+/// Printable character that is not one of chars
+fn draw_outside<R: Rng + ?Sized>(chars: &[char], rng: &mut R) -> char {
+    let outside: Vec<char> = ALPHABET.filter(|c| !chars.contains(c)).collect();
+    match outside.is_empty() {
+        true => rng.gen_range(ALPHABET),
+        false => outside[rng.gen_range(0..outside.len())],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{compile, regex::match_likelihood};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn draw_n(source: &str, n: usize) -> Vec<String> {
+        let nfa = compile(source).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..n).map(|_| sample(&nfa, &mut rng)).collect()
+    }
+
+    #[test]
+    fn test_sample_matches() {
+        let sources = [
+            "^abc$",
+            "^a(b|cd)*e$",
+            "^a{2~Geo(0.5)}b$",
+            "^[a-c]{1,3~Bin(0.5)}$",
+            "^[abc~Cat(a=0.2,b=0.7)]+x?$",
+            "^(a{2}b){2}$",
+            "^.[^xyz]\\d$",
+            "^[a-e~Zipf(1.0)]{3~Zipf}$",
+            "^[a-e~Dir(a=10)][^ab~Dir(b=3)]$",
+            "^a{1,4~Pois(2.0)}b{2~NegBin(2,0.5)}$",
+            "^a{3~Norm(1.0)}b{2,6~Laplace(0.5)}$",
+            "^a{1~Zeta(2.0)}b{2,9~Zeta(1.5)}$",
+            "^a{~Hist(0=0.2,3=0.5,7=0.3)}b{2,4~Hist(2=0.5,4=0.5)}$",
+            "^a{1~Mix(0.5*Pois(2.0),0.5*Norm(1.0))}[a-e~Mix(0.3*Cat(c=1.0),0.7*Geo)]$",
+            "^a?~Ber(0.8)b*~Geo(0.4)(xy)+~Pois(2.0)$",
+            "^\\d~Zipf(1.2)[:space:]~Geo(0.5).~Cat(a=0.5,b=0.3)$",
+            "^日本\\p{Nd}~Geo(0.5)[é\\p{Zs}]\\P{Nd}$",
+            "^\\w\\W\\S[:punct:]~Zipf(1.0)[[:xdigit:]\\t]\\u{e9}\\x41$",
+            "^(?i)a[b-d~Zipf(1.0)](?s:.)(?-i)e (?x) f # g\n$",
+        ];
+        for source in sources {
+            let nfa = compile(source).unwrap();
+            for s in draw_n(source, 50) {
+                assert!(
+                    match_likelihood(&nfa, &s, false).unwrap_or(0.0) > 0.0,
+                    "{} does not match {}",
+                    s,
+                    source
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_seeded() {
+        assert_eq!(draw_n("[a-z]+", 10), draw_n("[a-z]+", 10));
+    }
+
+    #[test]
+    fn test_sample_geometric_count() {
+        // repetitions past the minimum of 1 average (1 - p) / p = 3
+        let lengths: Vec<usize> = draw_n("a{1~Geo(0.25)}", 2000)
+            .iter()
+            .map(|s| s.len())
+            .collect();
+        let mean = lengths.iter().sum::<usize>() as f64 / lengths.len() as f64;
+        assert!((mean - 4.0).abs() < 0.3, "mean {}", mean);
+        assert!(lengths.iter().all(|&n| n >= 1));
+    }
+
+    #[test]
+    fn test_sample_categorical_class() {
+        let samples = draw_n("[ab~Cat(a=0.2,b=0.8)]", 2000);
+        let n_a = samples.iter().filter(|s| *s == "a").count() as f64;
+        assert!((n_a / 2000.0 - 0.2).abs() < 0.03, "{}", n_a);
+        assert!(samples.iter().all(|s| s == "a" || s == "b"));
+    }
+
+    #[test]
+    fn test_sample_poisson_count() {
+        let lengths: Vec<usize> = draw_n("a{1~Pois(3.0)}", 2000)
+            .iter()
+            .map(|s| s.len())
+            .collect();
+        let mean = lengths.iter().sum::<usize>() as f64 / lengths.len() as f64;
+        assert!((mean - 4.0).abs() < 0.2, "mean {}", mean);
+    }
+
+    #[test]
+    fn test_sample_mixture_count() {
+        // half of the counts are around 2, the other half around 10
+        let samples = draw_n("a{0~Mix(0.5*Norm(0.5),0.5*Pois(10.0))}", 2000);
+        let n_short = samples.iter().filter(|s| s.len() <= 1).count() as f64;
+        assert!((n_short / 2000.0 - 0.5).abs() < 0.05, "{}", n_short);
+    }
+
+    #[test]
+    fn test_sample_zeta_count() {
+        // 1 / 1^2 of a mass of pi^2 / 6 is a single repetition
+        let samples = draw_n("a{1~Zeta(2.0)}", 4000);
+        let n_single = samples.iter().filter(|s| s.len() == 1).count() as f64;
+        let p_single = 6.0 / std::f64::consts::PI.powi(2);
+        assert!((n_single / 4000.0 - p_single).abs() < 0.03, "{}", n_single);
+        assert!(samples.iter().any(|s| s.len() > 10));
+    }
+
+    #[test]
+    fn test_sample_optional_bernoulli() {
+        let samples = draw_n("a?~Ber(0.8)", 2000);
+        let n_a = samples.iter().filter(|s| *s == "a").count() as f64;
+        assert!((n_a / 2000.0 - 0.8).abs() < 0.03, "{}", n_a);
+    }
+
+    #[test]
+    fn test_sample_far_tail() {
+        // bounds that hold almost none of the mass
+        let lengths: Vec<usize> = draw_n("a{0,2~Pois(50.0)}", 200)
+            .iter()
+            .map(|s| s.len())
+            .collect();
+        assert!(lengths.iter().all(|n| (0..=2).contains(n)));
+        assert!(lengths.iter().filter(|n| **n == 2).count() > 150);
+
+        let samples = draw_n("[abc~Pois(50.0)]", 200);
+        assert!(samples
+            .iter()
+            .all(|s| ["a", "b", "c"].contains(&s.as_str())));
+        assert!(samples.iter().filter(|s| *s == "c").count() > 150);
+    }
+
+    #[test]
+    fn test_sample_far_tail_counts() {
+        // counts offset from the lower bound, with their mean far past the upper one
+        for (source, n_max) in [("a{0,2~Pois(80.0)}", 2), ("a{1,3~NegBin(50,0.1)}", 3)] {
+            let lengths: Vec<usize> = draw_n(source, 200).iter().map(|s| s.len()).collect();
+            assert!(lengths.iter().all(|n| *n <= n_max), "{}", source);
+            assert!(lengths.iter().filter(|n| **n == n_max).count() > 150);
+        }
+    }
+
+    #[test]
+    fn test_sample_far_tail_discretized() {
+        // not centered on the bounds, as a quantifier would center them
+        let mut rng = StdRng::seed_from_u64(7);
+        for dist in [
+            Dist::PNormal(0, 2, 100.0, 1.0),
+            Dist::PLaplace(0, 2, 100.0, 1.0),
+        ] {
+            let counts: Vec<u64> = (0..200).map(|_| draw(&dist, &mut rng)).collect();
+            // the mass piles up on the bound nearest the center
+            assert!(counts.iter().all(|n| (0..=2).contains(n)));
+            assert!(counts.iter().filter(|n| **n == 2).count() > 100);
+        }
+    }
+
+    #[test]
+    fn test_sample_range() {
+        let lengths: Vec<usize> = draw_n("a{2,4}", 200).iter().map(|s| s.len()).collect();
+        assert!(lengths.iter().all(|n| (2..=4).contains(n)));
+        assert!((2..=4).all(|n| lengths.contains(&n)));
+    }
+
+    #[test]
+    fn test_sample_weighted_alternation() {
+        let samples = draw_n("^(a|b|c)~Cat(0.2,0.5,0.3)$", 2000);
+        for (s, p) in [("a", 0.2), ("b", 0.5), ("c", 0.3)] {
+            let n = samples.iter().filter(|sample| *sample == s).count() as f64;
+            assert!((n / 2000.0 - p).abs() < 0.03, "{} {}", s, n);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How the probabilities of competing paths through the NFA are combined
+///
+/// Paths are always extended by multiplying probabilities, the semiring
+/// decides how two paths arriving at the same state are merged.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Semiring {
+    /// Keep the most likely path, i.e. the Viterbi score
+    #[default]
+    MaxProduct,
+    /// Sum over all paths, i.e. the marginal likelihood (forward algorithm)
+    SumProduct,
+}
+
+impl Semiring {
+    /// Merge the probabilities of two paths, in linear or log space
+    pub fn add(&self, a: f64, b: f64, log: bool) -> f64 {
+        match (self, log) {
+            (Semiring::MaxProduct, _) => f64::max(a, b),
+            (Semiring::SumProduct, false) => a + b,
+            (Semiring::SumProduct, true) => log_sum_exp(a, b),
+        }
+    }
+
+    /// Identity of add, i.e. the probability of no path
+    pub fn zero(&self, log: bool) -> f64 {
+        zero(log)
+    }
+}
+
+/// Probability of an impossible event, in linear or log space
+pub fn zero(log: bool) -> f64 {
+    match log {
+        true => f64::NEG_INFINITY,
+        false => 0.0,
+    }
+}
+
+/// Probability of a certain event, in linear or log space
+pub fn one(log: bool) -> f64 {
+    match log {
+        true => 0.0,
+        false => 1.0,
+    }
+}
+
+/// Extend a path with probability a by a step with probability b
+pub fn mul(a: f64, b: f64, log: bool) -> f64 {
+    match log {
+        true => a + b,
+        false => a * b,
+    }
+}
+
+/// Convert a probability in linear space to linear or log space
+pub fn scale(p: f64, log: bool) -> f64 {
+    match log {
+        true => p.ln(),
+        false => p,
+    }
+}
+
+/// ln(e^a + e^b) without underflow
+fn log_sum_exp(a: f64, b: f64) -> f64 {
+    if a == f64::NEG_INFINITY {
+        return b;
+    }
+    if b == f64::NEG_INFINITY {
+        return a;
+    }
+    let max = f64::max(a, b);
+    max + (-(a - b).abs()).exp().ln_1p()
+}
+
+impl FromStr for Semiring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(Semiring::MaxProduct),
+            "sum" => Ok(Semiring::SumProduct),
+            _ => Err(format!("unknown semiring {}, expected max or sum", s)),
+        }
+    }
+}
+
+impl fmt::Display for Semiring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Semiring::MaxProduct => write!(f, "max"),
+            Semiring::SumProduct => write!(f, "sum"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_semiring_add() {
+        assert_eq!(Semiring::MaxProduct.add(0.25, 0.5, false), 0.5);
+        assert_eq!(Semiring::SumProduct.add(0.25, 0.5, false), 0.75);
+        assert_eq!(
+            Semiring::SumProduct.add(Semiring::SumProduct.zero(false), 0.5, false),
+            0.5
+        );
+    }
+
+    #[test]
+    fn test_semiring_add_log() {
+        let (a, b) = (0.25_f64.ln(), 0.5_f64.ln());
+        assert_eq!(Semiring::MaxProduct.add(a, b, true), b);
+        assert_relative_eq!(Semiring::SumProduct.add(a, b, true), 0.75_f64.ln());
+        assert_eq!(Semiring::SumProduct.add(zero(true), b, true), b);
+        assert_eq!(
+            Semiring::SumProduct.add(zero(true), zero(true), true),
+            zero(true)
+        );
+    }
+
+    #[test]
+    fn test_mul() {
+        assert_eq!(mul(one(false), 0.5, false), 0.5);
+        assert_eq!(mul(one(true), 0.5_f64.ln(), true), 0.5_f64.ln());
+        assert_eq!(mul(zero(true), 0.5_f64.ln(), true), zero(true));
+        assert_eq!(scale(0.0, true), zero(true));
+    }
+
+    #[test]
+    fn test_semiring_from_str() {
+        assert_eq!("max".parse(), Ok(Semiring::MaxProduct));
+        assert_eq!("sum".parse(), Ok(Semiring::SumProduct));
+        assert!("min".parse::<Semiring>().is_err());
+    }
+}
@@ -1,19 +1,22 @@
 use colored::Colorize;
 
-use crate::ast::{AstNode, Kind};
-use crate::distribution::Dist;
+use crate::ast::Kind;
 use crate::nfa::State;
+use crate::regex_state::{States, Thread};
 use itertools::Itertools;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 static PIXEL_MAP: [u8; 5] = [0x00, 0x40, 0x44, 0x46, 0x47];
 
-pub fn debug_print(
-    states: &HashMap<usize, f64>,
-    counts: &HashMap<usize, u64>,
-    nfa: &Vec<State>,
-    token: &Kind,
-) {
+pub fn debug_print(threads: &States, nfa: &[State], token: &Kind, log: bool) {
+    // Number of threads and best probability at each state
+    let mut counts: HashMap<usize, u64> = HashMap::new();
+    let mut states: HashMap<usize, f64> = HashMap::new();
+    for (Thread(idx, _), p) in threads.iter() {
+        *counts.entry(*idx).or_insert(0) += 1;
+        let best = states.entry(*idx).or_insert(*p);
+        *best = f64::max(*best, *p);
+    }
     for (i, state) in nfa.iter().enumerate() {
         // let (p, n) = match states.get(&i) {
         //     Some((p, n)) => (f64::clamp(p * 4.0, 0., 4.) as usize, *n as u8),
@@ -29,7 +32,7 @@ pub fn debug_print(
     }
     println!();
     for (i, state) in nfa.iter().enumerate() {
-        let c = match states.get(&i) {
+        let c = match states.get(&i).map(|p| if log { p.exp() } else { *p }) {
             Some(p) => (
                 u8::clamp((p * 255.0) as u8, 25, 255),
                 0,